@@ -0,0 +1,279 @@
+use std::sync::OnceLock;
+
+use crate::bitboard::{self, Bitboard};
+use crate::board::{PieceColor, PieceType};
+
+// (file, rank) steps for each way a piece can move
+const KNIGHT_STEPS: [(isize, isize); 8] =
+    [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_STEPS: [(isize, isize); 8] =
+    [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+const ROOK_DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+// seed for the magic number search, fixed so every run builds the same tables
+const MAGIC_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+// maps the blockers of a sliding piece on one square to its slot in the shared
+// attack table: ((occupied & mask) * magic) >> shift, plus offset
+#[derive(Copy, Clone, Default)]
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct AttackTables {
+    knight: [Bitboard; 64],
+    king: [Bitboard; 64],
+    // indexed by color, white then black
+    pawn: [[Bitboard; 64]; 2],
+    rook_magics: [Magic; 64],
+    bishop_magics: [Magic; 64],
+    sliding: Vec<Bitboard>,
+}
+
+static TABLES: OnceLock<AttackTables> = OnceLock::new();
+
+// builds the attack tables ahead of time so the first search doesn't pay for it
+pub fn init() {
+    tables();
+}
+
+fn tables() -> &'static AttackTables {
+    TABLES.get_or_init(AttackTables::new)
+}
+
+pub fn knight_attacks(square: usize) -> Bitboard {
+    tables().knight[square]
+}
+
+pub fn king_attacks(square: usize) -> Bitboard {
+    tables().king[square]
+}
+
+// squares a pawn of the given color on `square` attacks
+pub fn pawn_attacks(square: usize, color: PieceColor) -> Bitboard {
+    match color {
+        PieceColor::White => tables().pawn[0][square],
+        PieceColor::Black => tables().pawn[1][square],
+        PieceColor::None => bitboard::EMPTY,
+    }
+}
+
+// squares a rook on `square` attacks, stopping at (and including) the first
+// occupied square in each direction
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.sliding[tables.rook_magics[square].index(occupied)]
+}
+
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.sliding[tables.bishop_magics[square].index(occupied)]
+}
+
+pub fn queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+// squares a piece of the given type and color on `square` attacks
+pub fn piece_attacks(piece_type: PieceType, color: PieceColor, square: usize,
+                     occupied: Bitboard) -> Bitboard {
+    match piece_type {
+        PieceType::Pawn => pawn_attacks(square, color),
+        PieceType::Knight => knight_attacks(square),
+        PieceType::Bishop => bishop_attacks(square, occupied),
+        PieceType::Rook => rook_attacks(square, occupied),
+        PieceType::Queen => queen_attacks(square, occupied),
+        PieceType::King => king_attacks(square),
+        PieceType::Empty => bitboard::EMPTY,
+    }
+}
+
+impl AttackTables {
+    fn new() -> Self {
+        let mut tables = AttackTables {
+            knight: [bitboard::EMPTY; 64],
+            king: [bitboard::EMPTY; 64],
+            pawn: [[bitboard::EMPTY; 64]; 2],
+            rook_magics: [Magic::default(); 64],
+            bishop_magics: [Magic::default(); 64],
+            sliding: Vec::new(),
+        };
+
+        for square in 0..64 {
+            tables.knight[square] = step_attacks(square, &KNIGHT_STEPS);
+            tables.king[square] = step_attacks(square, &KING_STEPS);
+            tables.pawn[0][square] = step_attacks(square, &[(-1, 1), (1, 1)]);
+            tables.pawn[1][square] = step_attacks(square, &[(-1, -1), (1, -1)]);
+        }
+
+        let mut random = Xorshift(MAGIC_SEED);
+        for square in 0..64 {
+            tables.rook_magics[square] =
+                find_magic(square, &ROOK_DIRECTIONS, &mut tables.sliding, &mut random);
+            tables.bishop_magics[square] =
+                find_magic(square, &BISHOP_DIRECTIONS, &mut tables.sliding, &mut random);
+        }
+
+        tables
+    }
+}
+
+// squares reached by a single step of each of the given (file, rank) steps
+fn step_attacks(square: usize, steps: &[(isize, isize)]) -> Bitboard {
+    let (file, rank) = bitboard::file_rank_from_square(square);
+
+    steps.iter()
+        .filter_map(|&step| offset_square(file, rank, step))
+        .fold(bitboard::EMPTY, |attacks, target| attacks | bitboard::square_bit(target))
+}
+
+// squares a slider reaches walking each direction until it hits a blocker
+fn ray_attacks(square: usize, directions: &[(isize, isize)], occupied: Bitboard) -> Bitboard {
+    let mut attacks = bitboard::EMPTY;
+
+    for &direction in directions {
+        let (mut file, mut rank) = bitboard::file_rank_from_square(square);
+        while let Some(target) = offset_square(file, rank, direction) {
+            attacks |= bitboard::square_bit(target);
+            if bitboard::contains(occupied, target) {
+                break;
+            }
+            (file, rank) = bitboard::file_rank_from_square(target);
+        }
+    }
+
+    attacks
+}
+
+// squares whose occupancy changes a slider's attacks: its rays without the
+// last square of each, since a blocker on the edge stops nothing further
+fn relevant_occupancy(square: usize, directions: &[(isize, isize)]) -> Bitboard {
+    let mut mask = bitboard::EMPTY;
+
+    for &direction in directions {
+        let (mut file, mut rank) = bitboard::file_rank_from_square(square);
+        while let Some(target) = offset_square(file, rank, direction) {
+            (file, rank) = bitboard::file_rank_from_square(target);
+            if offset_square(file, rank, direction).is_none() {
+                break;
+            }
+            mask |= bitboard::square_bit(target);
+        }
+    }
+
+    mask
+}
+
+fn offset_square(file: usize, rank: usize, (file_step, rank_step): (isize, isize)) -> Option<usize> {
+    let file = file.checked_add_signed(file_step).filter(|&f| f < 8)?;
+    let rank = rank.checked_add_signed(rank_step).filter(|&r| r < 8)?;
+
+    Some(bitboard::file_rank_to_square(file, rank))
+}
+
+// searches random candidates for a magic that sends every blocker subset of the
+// square's mask to a slot holding the right attacks, then appends its slots to
+// the shared table
+fn find_magic(square: usize, directions: &[(isize, isize)], sliding: &mut Vec<Bitboard>,
+              random: &mut Xorshift) -> Magic {
+    let mask = relevant_occupancy(square, directions);
+    let bits = mask.count_ones();
+    let size = 1 << bits;
+
+    // walk every subset of the mask (carry-rippler) with its attacks
+    let mut occupancies = Vec::with_capacity(size);
+    let mut attacks = Vec::with_capacity(size);
+    let mut subset = bitboard::EMPTY;
+    loop {
+        occupancies.push(subset);
+        attacks.push(ray_attacks(square, directions, subset));
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == bitboard::EMPTY {
+            break;
+        }
+    }
+
+    let mut table = vec![bitboard::EMPTY; size];
+    // the attempt that last wrote each slot, so the table needn't be cleared
+    let mut epoch = vec![0u32; size];
+    let mut attempt = 0;
+    loop {
+        // sparse candidates work far more often than uniform ones
+        let magic = random.next() & random.next() & random.next();
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        attempt += 1;
+        let candidate = Magic { mask, magic, shift: 64 - bits, offset: 0 };
+        let fits = occupancies.iter().zip(&attacks).all(|(&occupied, &attack)| {
+            let index = candidate.index(occupied);
+            if epoch[index] != attempt {
+                epoch[index] = attempt;
+                table[index] = attack;
+                true
+            } else {
+                table[index] == attack
+            }
+        });
+
+        if fits {
+            let offset = sliding.len();
+            sliding.extend(table);
+            return Magic { offset, ..candidate };
+        }
+    }
+}
+
+// small fast generator for magic candidates
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sliding_lookups_match_ray_walks() {
+        let mut random = Xorshift(1);
+        for square in 0..64 {
+            for _ in 0..64 {
+                let occupied = random.next() & random.next();
+                assert_eq!(rook_attacks(square, occupied),
+                           ray_attacks(square, &ROOK_DIRECTIONS, occupied));
+                assert_eq!(bishop_attacks(square, occupied),
+                           ray_attacks(square, &BISHOP_DIRECTIONS, occupied));
+            }
+        }
+    }
+
+    #[test]
+    fn step_tables() {
+        // a1 and e4
+        assert_eq!(knight_attacks(0).count_ones(), 2);
+        assert_eq!(knight_attacks(28).count_ones(), 8);
+        assert_eq!(king_attacks(0).count_ones(), 3);
+        assert_eq!(king_attacks(28).count_ones(), 8);
+        // pawns on the a file only attack towards b
+        assert_eq!(pawn_attacks(8, PieceColor::White), bitboard::square_bit(17));
+        assert_eq!(pawn_attacks(8, PieceColor::Black), bitboard::square_bit(1));
+    }
+}
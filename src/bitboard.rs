@@ -0,0 +1,84 @@
+// a set of squares, one bit per square counting a1 = 0, b1 = 1, ... h8 = 63.
+// squares in this numbering are plain indices 0-63, unlike the 0x88 hex
+// positions used by the board's square array
+pub type Bitboard = u64;
+
+pub const EMPTY: Bitboard = 0;
+
+pub const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+pub const FILE_H: Bitboard = FILE_A << 7;
+pub const RANK_1: Bitboard = 0xFF;
+pub const RANK_4: Bitboard = RANK_1 << 24;
+pub const RANK_5: Bitboard = RANK_1 << 32;
+pub const RANK_8: Bitboard = RANK_1 << 56;
+
+// the bitboard holding only `square`
+pub fn square_bit(square: usize) -> Bitboard {
+    1 << square
+}
+
+pub fn contains(bitboard: Bitboard, square: usize) -> bool {
+    bitboard & square_bit(square) != 0
+}
+
+// removes the lowest square from the bitboard and returns it
+pub fn pop_lowest(bitboard: &mut Bitboard) -> Option<usize> {
+    if *bitboard == EMPTY {
+        return None;
+    }
+
+    let square = bitboard.trailing_zeros() as usize;
+    *bitboard &= *bitboard - 1;
+    Some(square)
+}
+
+// iterates the squares of a bitboard from a1 to h8
+pub fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || pop_lowest(&mut bitboard))
+}
+
+// gets the square from a 0x88 hex position
+pub fn hex_to_square(position: usize) -> usize {
+    (position >> 4) * 8 + (position & 0x7)
+}
+
+// gets the 0x88 hex position from a square
+pub fn square_to_hex(square: usize) -> usize {
+    (square >> 3) * 16 + (square & 0x7)
+}
+
+// gets the (file, rank) tuple from a square
+pub fn file_rank_from_square(square: usize) -> (usize, usize) {
+    (square & 0x7, square >> 3)
+}
+
+pub fn file_rank_to_square(file: usize, rank: usize) -> usize {
+    rank * 8 + file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{file_rank_from_hex, file_rank_to_hex};
+
+    #[test]
+    fn square_and_hex_conversions_agree() {
+        for rank in 0..8 {
+            for file in 0..8 {
+                let square = file_rank_to_square(file, rank);
+                let position = file_rank_to_hex(file, rank);
+                assert_eq!(hex_to_square(position), square);
+                assert_eq!(square_to_hex(square), position);
+                assert_eq!(file_rank_from_square(square), file_rank_from_hex(position));
+            }
+        }
+    }
+
+    #[test]
+    fn squares_are_listed_in_order() {
+        let bitboard = square_bit(63) | square_bit(0) | square_bit(28);
+        assert_eq!(squares(bitboard).collect::<Vec<_>>(), vec![0, 28, 63]);
+        assert!(contains(FILE_H, 7) && contains(FILE_H, 63) && !contains(FILE_H, 56));
+        assert!(contains(RANK_8, 56) && !contains(RANK_1, 8));
+    }
+}
@@ -1,8 +1,143 @@
-use std::collections::HashMap;
+use std::fmt;
+
+use crate::attacks;
+use crate::bitboard::{self, Bitboard};
+use crate::moves::{self, Move, MoveFlag, MoveList};
+use crate::zobrist;
 
 const BOARD_SIZE: usize = 128; // Size of 0x88 board
 
-#[derive(Copy, Clone, PartialEq)]
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// castling rights bits
+pub const WHITE_KINGSIDE: u8 = 0b0001;
+pub const WHITE_QUEENSIDE: u8 = 0b0010;
+pub const BLACK_KINGSIDE: u8 = 0b0100;
+pub const BLACK_QUEENSIDE: u8 = 0b1000;
+
+// squares a pawn promotes on
+const PROMOTION_RANKS: Bitboard = bitboard::RANK_1 | bitboard::RANK_8;
+
+const PROMOTION_PIECES: [PieceType; 4] =
+    [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+
+// which moves a generator produces
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum MoveKind {
+    All,
+    Captures,
+    Quiets,
+}
+
+// the space separated fields of a FEN string
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FenField {
+    Placement,
+    SideToMove,
+    Castling,
+    EnPassant,
+}
+
+// why a FEN string couldn't be loaded
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FenError {
+    MissingField(FenField),
+    TooManyFields,
+    // the placement field doesn't have 8 ranks
+    RankCount(usize),
+    // a rank (1-8) doesn't describe exactly 8 files
+    FileCount { rank: usize, files: usize },
+    InvalidPiece(char),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    // the FEN is well formed but describes an impossible position
+    IllegalPosition(PositionError),
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenField::Placement => write!(f, "piece placement"),
+            FenField::SideToMove => write!(f, "side to move"),
+            FenField::Castling => write!(f, "castling rights"),
+            FenField::EnPassant => write!(f, "en passant square"),
+        }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {} field", field),
+            FenError::TooManyFields => write!(f, "unexpected fields after the fullmove number"),
+            FenError::RankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::FileCount { rank, files } => {
+                write!(f, "rank {} has {} files instead of 8", rank, files)
+            }
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::InvalidSideToMove(field) => write!(f, "invalid side to move '{}'", field),
+            FenError::InvalidCastling(field) => write!(f, "invalid castling rights '{}'", field),
+            FenError::InvalidEnPassant(field) => write!(f, "invalid en passant square '{}'", field),
+            FenError::InvalidHalfmoveClock(field) => write!(f, "invalid halfmove clock '{}'", field),
+            FenError::InvalidFullmoveNumber(field) => {
+                write!(f, "invalid fullmove number '{}'", field)
+            }
+            FenError::IllegalPosition(error) => write!(f, "illegal position: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+// why a position can't occur in a legal game
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PositionError {
+    KingCount { color: PieceColor, count: usize },
+    TooManyPieces { color: PieceColor, count: usize },
+    // hex position of a pawn on the 1st or 8th rank
+    PawnOnBackRank(usize),
+    // a castling right whose king or rook has left its starting square
+    CastlingRights(u8),
+    EnPassant(usize),
+    // the side that just moved left its king attacked
+    OpponentInCheck,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::KingCount { color, count } => {
+                write!(f, "{:?} has {} kings instead of 1", color, count)
+            }
+            PositionError::TooManyPieces { color, count } => {
+                write!(f, "{:?} has {} pieces, more than 16", color, count)
+            }
+            PositionError::PawnOnBackRank(position) => {
+                write!(f, "pawn on back rank at {}", hex_to_chess_notation(*position))
+            }
+            PositionError::CastlingRights(right) => {
+                let symbol = match *right {
+                    WHITE_KINGSIDE => "K",
+                    WHITE_QUEENSIDE => "Q",
+                    BLACK_KINGSIDE => "k",
+                    _ => "q",
+                };
+                write!(f, "castling right {} without king and rook in place", symbol)
+            }
+            PositionError::EnPassant(position) => {
+                write!(f, "impossible en passant square {}", hex_to_chess_notation(*position))
+            }
+            PositionError::OpponentInCheck => write!(f, "side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PieceType {
     Empty,
     Pawn,
@@ -13,13 +148,24 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PieceColor {
     White,
     Black,
     None,
 }
 
+impl PieceColor {
+    // the other side, `None` stays `None`
+    pub fn opposite(&self) -> PieceColor {
+        match self {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+            PieceColor::None => PieceColor::None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub struct DynamicPiece {
     piece_type: PieceType,
@@ -45,40 +191,143 @@ impl DynamicPiece {
     }
 }
 
+// the pieces of one color, kept in step with the squares by every board
+// mutation. `index` maps the hex position of each piece to its slot in `pieces`
+#[derive(Clone)]
+struct PieceList {
+    pieces: Vec<DynamicPiece>,
+    index: [u8; BOARD_SIZE],
+}
+
+impl PieceList {
+    fn new() -> Self {
+        PieceList { pieces: Vec::with_capacity(16), index: [0; BOARD_SIZE] }
+    }
+
+    fn add(&mut self, piece: DynamicPiece) {
+        self.index[piece.position] = self.pieces.len() as u8;
+        self.pieces.push(piece);
+    }
+
+    // takes out the piece on `position`, moving the last piece into its slot,
+    // and returns it along with the slot it had
+    fn remove(&mut self, position: usize) -> (DynamicPiece, usize) {
+        let slot = self.index[position] as usize;
+        let piece = self.pieces.swap_remove(slot);
+        if let Some(moved) = self.pieces.get(slot) {
+            self.index[moved.position] = slot as u8;
+        }
+
+        (piece, slot)
+    }
+
+    // undoes `remove`, putting the piece back in the slot it had
+    fn restore(&mut self, piece: DynamicPiece, slot: usize) {
+        self.pieces.push(piece);
+        let last = self.pieces.len() - 1;
+        self.pieces.swap(slot, last);
+        self.index[self.pieces[last].position] = last as u8;
+        self.index[piece.position] = slot as u8;
+    }
+
+    fn relocate(&mut self, from: usize, to: usize) {
+        let slot = self.index[from] as usize;
+        self.pieces[slot].position = to;
+        self.index[to] = slot as u8;
+    }
+
+    fn set_type(&mut self, position: usize, piece_type: PieceType) {
+        self.pieces[self.index[position] as usize].piece_type = piece_type;
+    }
+
+    // the piece this list holds on `position`, if any
+    fn get(&self, position: usize) -> Option<&DynamicPiece> {
+        self.pieces.get(self.index[position] as usize).filter(|p| p.position == position)
+    }
+}
+
+#[derive(Clone)]
 pub struct Board {
     squares: [DynamicPiece; BOARD_SIZE],
-    white_set: Vec<DynamicPiece>,
-    black_set: Vec<DynamicPiece>,
-    fen: String,
+    white_set: PieceList,
+    black_set: PieceList,
+    // bitboards of each piece type (pawn to king) and color, and of every piece
+    piece_bitboards: [Bitboard; 6],
+    color_bitboards: [Bitboard; 2],
+    occupied: Bitboard,
+    side_to_move: PieceColor,
+    castling_rights: u8,
+    en_passant: Option<usize>,
+    halfmove_clock: usize,
+    fullmove_number: usize,
+    // Zobrist hash of everything above except the clocks
+    hash: u64,
+}
+
+// the state a move destroys, handed back to unmake_move to restore it
+#[derive(Copy, Clone)]
+pub struct Undo {
+    // the captured piece and its index in its white/black set
+    captured: Option<(DynamicPiece, usize)>,
+    side_to_move: PieceColor,
+    castling_rights: u8,
+    en_passant: Option<usize>,
+    halfmove_clock: usize,
+    fullmove_number: usize,
+    hash: u64,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
 }
 
 impl Board {
-    // construct an empty board upon instantiation
+    // construct a board set up at the starting position
     pub fn new() -> Self {
-        let default_fen = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-        let mut board = Board {
-            squares: [DynamicPiece {piece_type: PieceType::Empty, 
-                piece_color: PieceColor::None, position: 0}; BOARD_SIZE],
-                white_set: vec![],
-                black_set: vec![],
-                fen: default_fen.clone(),
-        };
+        Board::from_fen(START_FEN).expect("starting position FEN is valid")
+    }
 
-        board.init_board_from_fen(default_fen);
-        board
+    // construct a board without any pieces on it
+    fn empty() -> Self {
+        Board {
+            squares: std::array::from_fn(empty_square),
+            white_set: PieceList::new(),
+            black_set: PieceList::new(),
+            piece_bitboards: [bitboard::EMPTY; 6],
+            color_bitboards: [bitboard::EMPTY; 2],
+            occupied: bitboard::EMPTY,
+            side_to_move: PieceColor::White,
+            castling_rights: 0,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+        }
     }
 
-    // performs a deep copy of the board to create a new board
-    pub fn copy(&self) -> Board {
-        let mut board = Board::new();
+    pub fn get_side_to_move(&self) -> PieceColor {
+        self.side_to_move
+    }
 
-        board.init_board_from_fen(self.fen.clone());
+    // castling rights as a combination of the WHITE_/BLACK_ side bits
+    pub fn get_castling_rights(&self) -> u8 {
+        self.castling_rights
+    }
 
-        board
+    // square a pawn can be captured on en passant, if the last move was a double push
+    pub fn get_en_passant(&self) -> Option<usize> {
+        self.en_passant
     }
 
-    pub fn get_fen(&self) -> String {
-        self.fen.clone()
+    // number of half moves since the last capture or pawn move
+    pub fn get_halfmove_clock(&self) -> usize {
+        self.halfmove_clock
+    }
+
+    pub fn get_fullmove_number(&self) -> usize {
+        self.fullmove_number
     }
 
     // get piece (type) from an index 
@@ -86,26 +335,63 @@ impl Board {
         self.squares[index]
     }
 
-    // set piece (type) from a index
+    // set piece (type) from a index, replacing whatever was there
     pub fn set_piece(&mut self, index: usize, piece: DynamicPiece) {
-        self.squares[index] = piece;
-        self.squares[index].position = index;
+        if self.get_piece(index).piece_type != PieceType::Empty {
+            self.remove_piece(index);
+        }
+        if piece.piece_type != PieceType::Empty {
+            self.add_piece(DynamicPiece { position: index, ..piece });
+        }
+    }
+
+    // squares holding pieces of the given type and color
+    pub fn get_pieces_bitboard(&self, piece_type: PieceType, color: PieceColor) -> Bitboard {
+        self.piece_bitboards[piece_type_index(piece_type)] & self.get_color_bitboard(color)
+    }
+
+    // squares holding pieces of the given color
+    pub fn get_color_bitboard(&self, color: PieceColor) -> Bitboard {
+        self.color_bitboards[color_index(color)]
+    }
+
+    // squares holding any piece
+    pub fn get_occupied(&self) -> Bitboard {
+        self.occupied
+    }
 
-        self.update_fen();
+    // Zobrist hash of the position, kept up to date as moves are made
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
+    // works the hash out from the whole position rather than incrementally,
+    // for setting up a board and checking the running hash against
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = zobrist::castling_key(self.castling_rights) ^ en_passant_hash(self.en_passant);
+        if self.side_to_move == PieceColor::Black {
+            hash ^= zobrist::side_key();
+        }
+        for piece in self.white_set.pieces.iter().chain(&self.black_set.pieces) {
+            hash ^= zobrist::piece_key(piece.piece_type, piece.piece_color,
+                                       bitboard::hex_to_square(piece.position));
+        }
+
+        hash
     }
 
     // get all white pieces
     pub fn get_white_pieces(&self) -> Vec<DynamicPiece> {
-        self.white_set.clone()
+        self.white_set.pieces.clone()
     }
 
     // get all black pieces
     pub fn get_black_pieces(&self) -> Vec<DynamicPiece> {
-        self.black_set.clone()
+        self.black_set.pieces.clone()
     }
 
-    // update the fen string upon a change
-    pub fn update_fen(&mut self) {
+    // describe the current position as a FEN string
+    pub fn get_fen(&self) -> String {
         let mut new_fen = String::from("");
         let mut space_count = 0;
 
@@ -210,9 +496,30 @@ impl Board {
                 new_fen += "/";
             }
         }
-        // will be changed
-        new_fen += " w KQkq - 0 1";
-        self.fen = new_fen;
+
+        new_fen += match self.side_to_move {
+            PieceColor::Black => " b ",
+            _ => " w ",
+        };
+
+        if self.castling_rights == 0 {
+            new_fen += "-";
+        }
+        for (right, symbol) in [(WHITE_KINGSIDE, "K"), (WHITE_QUEENSIDE, "Q"),
+                                (BLACK_KINGSIDE, "k"), (BLACK_QUEENSIDE, "q")] {
+            if self.castling_rights & right != 0 {
+                new_fen += symbol;
+            }
+        }
+
+        new_fen += " ";
+        new_fen += &match self.en_passant {
+            Some(position) => hex_to_chess_notation(position),
+            None => "-".to_string(),
+        };
+
+        new_fen += &format!(" {} {}", self.halfmove_clock, self.fullmove_number);
+        new_fen
     }
 
     // print visual board in terminal
@@ -227,615 +534,693 @@ impl Board {
                                                         get_piece_symbol(piece_type, piece_color)),
                 }
             }
-            println!("");
+            println!();
         }
         print!("\t   -----------------\n\t    a b c d e f g h\n\n")
     }
 
-    // initialize a board given a FEN string
-    pub fn init_board_from_fen(&mut self, fen: String) {
-        let mut rank = 7;
-        let mut file = 0;
+    // build a board from a FEN string, checking every field along the way
+    // and that the position described could occur in a game.
+    // the halfmove clock and fullmove number may be left out, as in EPD
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let board = Board::parse_fen(fen)?;
+        board.validate().map_err(FenError::IllegalPosition)?;
 
-        // wipe white/black set
-        self.white_set.clear();
-        self.black_set.clear();
+        Ok(board)
+    }
 
-        for c in fen.chars() {
-            match c {
-                ' ' => {
-                    break;
-                }
-                '/' => {
-                    rank -= 1;
-                    file = 0;
-                }
-                '1'..='8' => {
-                    let count = c.to_digit(10).unwrap() as usize;
-                    for _ in 0..count {
-                        self.set_piece(rank * 16 + file, 
-                                       DynamicPiece { 
-                                           piece_type: PieceType::Empty, 
-                                           piece_color: PieceColor::None,
-                                           position: file_rank_to_hex(file, rank),
-                                       });
-                        file += 1;
-                    }
-                }
-                'r' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Rook, 
-                                       piece_color: PieceColor::Black,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.black_set.push(DynamicPiece { 
-                        piece_type: PieceType::Rook, 
-                        piece_color: PieceColor::Black,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'n' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Knight, 
-                                       piece_color: PieceColor::Black,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.black_set.push(DynamicPiece { 
-                        piece_type: PieceType::Knight, 
-                        piece_color: PieceColor::Black,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'b' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Bishop, 
-                                       piece_color: PieceColor::Black,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.black_set.push(DynamicPiece { 
-                        piece_type: PieceType::Bishop, 
-                        piece_color: PieceColor::Black,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'q' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Queen, 
-                                       piece_color: PieceColor::Black,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.black_set.push(DynamicPiece { 
-                        piece_type: PieceType::Queen, 
-                        piece_color: PieceColor::Black,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'k' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::King, 
-                                       piece_color: PieceColor::Black,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.black_set.push(DynamicPiece { 
-                        piece_type: PieceType::King, 
-                        piece_color: PieceColor::Black,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'p' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Pawn, 
-                                       piece_color: PieceColor::Black,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.black_set.push(DynamicPiece { 
-                        piece_type: PieceType::Pawn, 
-                        piece_color: PieceColor::Black,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'R' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Rook, 
-                                       piece_color: PieceColor::White,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.white_set.push(DynamicPiece { 
-                        piece_type: PieceType::Rook, 
-                        piece_color: PieceColor::White,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'N' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Knight, 
-                                       piece_color: PieceColor::White,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.white_set.push(DynamicPiece { 
-                        piece_type: PieceType::Knight, 
-                        piece_color: PieceColor::White,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'B' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Bishop, 
-                                       piece_color: PieceColor::White,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.white_set.push(DynamicPiece { 
-                        piece_type: PieceType::Bishop, 
-                        piece_color: PieceColor::White,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'Q' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Queen, 
-                                       piece_color: PieceColor::White,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.white_set.push(DynamicPiece { 
-                        piece_type: PieceType::Queen, 
-                        piece_color: PieceColor::White,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'K' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::King, 
-                                       piece_color: PieceColor::White,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.white_set.push(DynamicPiece { 
-                        piece_type: PieceType::King, 
-                        piece_color: PieceColor::White,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
-                }
-                'P' => {
-                    self.set_piece(rank * 16 + file, 
-                                   DynamicPiece { 
-                                       piece_type: PieceType::Pawn, 
-                                       piece_color: PieceColor::White,
-                                       position: file_rank_to_hex(file, rank),
-                                   });
-                    self.white_set.push(DynamicPiece { 
-                        piece_type: PieceType::Pawn, 
-                        piece_color: PieceColor::White,
-                        position: file_rank_to_hex(file, rank),
-                    });
-                    file += 1;
+    // build a board from a FEN string without checking the position is legal
+    fn parse_fen(fen: &str) -> Result<Board, FenError> {
+        let mut board = Board::empty();
+        let mut fields = fen.split_whitespace();
+
+        let placement = fields.next().ok_or(FenError::MissingField(FenField::Placement))?;
+        board.parse_placement(placement)?;
+
+        board.side_to_move = match fields.next() {
+            Some("w") => PieceColor::White,
+            Some("b") => PieceColor::Black,
+            Some(other) => return Err(FenError::InvalidSideToMove(other.to_string())),
+            None => return Err(FenError::MissingField(FenField::SideToMove)),
+        };
+
+        let castling = fields.next().ok_or(FenError::MissingField(FenField::Castling))?;
+        board.castling_rights = parse_castling_rights(castling)?;
+
+        board.en_passant = match fields.next() {
+            Some("-") => None,
+            Some(square) => match chess_notation_to_hex(square) {
+                // only a square skipped by a double push can be an en passant target
+                Some(position) if matches!(file_rank_from_hex(position), (_, 2) | (_, 5)) => {
+                    Some(position)
                 }
-                _ => {}
-            }
+                _ => return Err(FenError::InvalidEnPassant(square.to_string())),
+            },
+            None => return Err(FenError::MissingField(FenField::EnPassant)),
+        };
+
+        if let Some(clock) = fields.next() {
+            board.halfmove_clock = clock.parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(clock.to_string()))?;
+        }
+        if let Some(number) = fields.next() {
+            board.fullmove_number = match number.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(FenError::InvalidFullmoveNumber(number.to_string())),
+            };
         }
-    }
 
-    pub fn get_legal_moves(&self, position: usize) -> Vec<usize> {
-        let mut legal_moves = Vec::new();
+        if fields.next().is_some() {
+            return Err(FenError::TooManyFields);
+        }
 
-        let piece_type = self.get_piece(position).piece_type;
-        let piece_color = self.get_piece(position).piece_color;
-        let file = position & 0xF; 
-        let rank = (position & 0xF0) >> 4; // gets rank bits with bit manipulation
+        board.hash = board.compute_hash();
+        Ok(board)
+    }
 
-        // legal moves by piece type
-        match piece_type {
-            // pawns
-            PieceType::Pawn => {
-                match piece_color {
-                    PieceColor::White => {
-                        if self.square_empty(file, rank + 1) {
-                            if rank < 7 {
-                                legal_moves.push(file_rank_to_hex(file, rank + 1));
-                            }
-                            if rank == 1 && self.square_empty(file, rank + 2) {
-                                legal_moves.push(file_rank_to_hex(file, rank + 2));
-                            }
-                        }
-                        if file > 0 && rank < 7 && 
-                            self.is_capturable(file_rank_to_hex(file, rank), 
-                                               file_rank_to_hex(file - 1, rank + 1)) {
-                                legal_moves.push(file_rank_to_hex(file - 1, rank + 1))
-                            }
-                        if file < 7 && rank < 7 && 
-                            self.is_capturable(file_rank_to_hex(file, rank), 
-                                               file_rank_to_hex(file + 1, rank + 1)) {
-                                legal_moves.push(file_rank_to_hex(file + 1, rank + 1))
-                            }
-                    }
-                    PieceColor::Black => {
-                        if self.square_empty(file, rank - 1) {
-                            if rank > 0 {
-                                legal_moves.push(file_rank_to_hex(file, rank - 1));
-                            }
-                            if rank == 6 && self.square_empty(file, rank - 2) {
-                                legal_moves.push(file_rank_to_hex(file, rank - 2));
-                            }
-                        }
-                        if file > 0 && rank > 0 && 
-                            self.is_capturable(file_rank_to_hex(file, rank), 
-                                              file_rank_to_hex(file - 1, rank - 1)) {
-                            legal_moves.push(file_rank_to_hex(file - 1, rank - 1))
-                        }
-                        if file < 7 && rank > 0 && 
-                            self.is_capturable(file_rank_to_hex(file, rank), 
-                                              file_rank_to_hex(file + 1, rank - 1)) {
-                            legal_moves.push(file_rank_to_hex(file + 1, rank - 1))
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            // Rooks
-            PieceType::Rook => {
-                for straight_move in self.get_straights(rank, file) {
-                    legal_moves.push(straight_move);
-                }
-            }
-            // Bishops
-            PieceType::Bishop => {
-                for diag_move in self.get_diagonals(rank, file) {
-                    legal_moves.push(diag_move);
-                }
-            }
+    // fill the squares and white/black sets from the piece placement field
+    fn parse_placement(&mut self, placement: &str) -> Result<(), FenError> {
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
 
-            // Queen
-            PieceType::Queen => {
-                for straight_move in self.get_straights(rank, file) {
-                    legal_moves.push(straight_move);
+        // ranks are listed from the 8th down to the 1st
+        for (rank, rank_text) in (0..8).rev().zip(ranks) {
+            let mut file = 0;
+            for c in rank_text.chars() {
+                if let Some(count @ 1..=8) = c.to_digit(10) {
+                    file += count as usize;
+                    continue;
                 }
 
-                for diag_move in self.get_diagonals(rank, file) {
-                    legal_moves.push(diag_move);
+                let (piece_type, piece_color) = piece_from_fen_char(c)
+                    .ok_or(FenError::InvalidPiece(c))?;
+                // keep counting past the 8th file so the error can report the total
+                if file < 8 {
+                    let position = file_rank_to_hex(file, rank);
+                    self.add_piece(DynamicPiece { piece_type, piece_color, position });
                 }
+                file += 1;
             }
 
-            // King
-            PieceType::King => {
-                // up
-                if rank < 7 && self.square_empty(file, rank + 1) {
-                    legal_moves.push(file_rank_to_hex(file, rank + 1));
-                }
-                // top-left
-                if rank < 7 && file > 0 && self.square_empty(file - 1, rank + 1) {
-                    legal_moves.push(file_rank_to_hex(file - 1, rank + 1));
-                }
-                // top-right
-                if rank < 7 && file < 7 && self.square_empty(file + 1, rank + 1) {
-                    legal_moves.push(file_rank_to_hex(file + 1, rank + 1));
-                }
-                // left
-                if file > 0 && self.square_empty(file - 1, rank) {
-                    legal_moves.push(file_rank_to_hex(file - 1, rank));
-                }
-                // right
-                if file < 7 && self.square_empty(file + 1, rank) {
-                    legal_moves.push(file_rank_to_hex(file + 1, rank));
-                }
-                // bottom-left
-                if file > 0 && rank > 0 && self.square_empty(file - 1, rank - 1) {
-                    legal_moves.push(file_rank_to_hex(file - 1, rank - 1));
-                }
-                // bottom
-                if rank > 0 && self.square_empty(file, rank - 1) {
-                    legal_moves.push(file_rank_to_hex(file, rank - 1));
-                }
-                // bottom-right
-                if rank > 0 && file < 7 && self.square_empty(file + 1, rank - 1) {
-                    legal_moves.push(file_rank_to_hex(file + 1, rank - 1));
-                }
+            if file != 8 {
+                return Err(FenError::FileCount { rank: rank + 1, files: file });
             }
+        }
 
-            // Knights
-            PieceType::Knight => {
-                // up-up-right
-                if rank < 6 && file < 7 && (self.square_empty(file + 1, rank + 2)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file + 1, rank + 2))){
-                    legal_moves.push(file_rank_to_hex(file + 1, rank + 2));
-                }
+        Ok(())
+    }
 
-                // up-up-left
-                if rank < 6 && file > 0 && (self.square_empty(file - 1, rank + 2)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file - 1, rank + 2))) {
-                    legal_moves.push(file_rank_to_hex(file - 1, rank + 2));
-                }
+    // gets the moves for the piece on `position` that don't leave its own king
+    // in check, none if the piece doesn't belong to the side to move
+    pub fn get_legal_moves(&mut self, position: usize) -> MoveList {
+        if self.get_piece(position).piece_color != self.side_to_move {
+            return MoveList::new();
+        }
 
-                // up-right-right
-                if rank < 7 && file < 6 && (self.square_empty(file + 2, rank + 1)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file + 2, rank + 1))) {
-                    legal_moves.push(file_rank_to_hex(file + 2, rank + 1));
-                }
+        let mut moves = self.get_pseudo_legal_moves(position);
+        self.retain_legal(&mut moves);
+        moves
+    }
 
-                // up-left-left
-                if rank < 7 && file > 1 && (self.square_empty(file - 2, rank + 1)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file - 2, rank + 1))) {
-                    legal_moves.push(file_rank_to_hex(file - 2, rank + 1));
-                }
+    // gets the moves for the piece on `position` following how it moves,
+    // without checking whether they expose its own king
+    pub fn get_pseudo_legal_moves(&self, position: usize) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_piece_moves(position, MoveKind::All, &mut moves);
+        moves
+    }
 
-                // down-down-right
-                if rank > 1 && file < 7 && (self.square_empty(file + 1, rank - 2)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file + 1, rank - 2))) {
-                    legal_moves.push(file_rank_to_hex(file + 1, rank - 2));
-                }
+    // gets every legal move for the side to move
+    pub fn get_board_legal_moves(&mut self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_moves(&mut moves);
+        self.retain_legal(&mut moves);
+        moves
+    }
 
-                // down-down-left
-                if rank > 1 && file > 0 && (self.square_empty(file - 1, rank - 2)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file - 1, rank - 2))) {
-                    legal_moves.push(file_rank_to_hex(file - 1, rank - 2));
-                }
+    // adds every pseudo-legal move of the side to move to `moves`, going
+    // through its pieces from a1 to h8 and each piece's targets in the same order
+    pub fn generate_moves(&self, moves: &mut MoveList) {
+        self.generate(MoveKind::All, moves);
+    }
 
-                // down-right-right
-                if rank > 0 && file < 6 && (self.square_empty(file + 2, rank - 1)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file + 2, rank - 1))) {
-                    legal_moves.push(file_rank_to_hex(file + 2, rank - 1));
-                }
+    // adds the pseudo-legal moves that change material: captures, en passant
+    // and promotions
+    pub fn generate_captures(&self, moves: &mut MoveList) {
+        self.generate(MoveKind::Captures, moves);
+    }
+
+    // adds the pseudo-legal moves generate_captures leaves out
+    pub fn generate_quiets(&self, moves: &mut MoveList) {
+        self.generate(MoveKind::Quiets, moves);
+    }
+
+    // checks the move doesn't leave the king of the side to move in check
+    pub fn is_legal(&mut self, m: Move) -> bool {
+        let color = self.side_to_move;
+        let undo = self.make_move(m);
+        let legal = !self.is_king_attacked(color);
+        self.unmake_move(m, undo);
+        legal
+    }
+
+    pub fn retain_legal(&mut self, moves: &mut MoveList) {
+        moves.retain(|m| self.is_legal(m));
+    }
+
+    fn generate(&self, kind: MoveKind, moves: &mut MoveList) {
+        for square in bitboard::squares(self.get_color_bitboard(self.side_to_move)) {
+            self.generate_piece_moves(bitboard::square_to_hex(square), kind, moves);
+        }
+    }
+
+    fn generate_piece_moves(&self, position: usize, kind: MoveKind, moves: &mut MoveList) {
+        let piece = self.get_piece(position);
+        if piece.piece_type == PieceType::Empty {
+            return;
+        }
+
+        let square = bitboard::hex_to_square(position);
+        let attacks = attacks::piece_attacks(piece.piece_type, piece.piece_color, square,
+                                             self.occupied);
+        let mut captures = attacks & self.get_color_bitboard(piece.piece_color.opposite());
+        let mut quiets = attacks & !self.occupied;
 
-                // down-left-left
-                if rank > 0 && file > 1 && (self.square_empty(file - 2, rank - 1)
-                                            || self.is_capturable(file_rank_to_hex(file, rank), 
-                                                                  file_rank_to_hex(file - 2, rank - 1))) {
-                    legal_moves.push(file_rank_to_hex(file - 2, rank - 1));
+        match piece.piece_type {
+            // pawns only move diagonally to capture, and pushing onto the last
+            // rank promotes so it counts with the captures
+            PieceType::Pawn => {
+                let pushes = self.get_pawn_pushes(square, piece.piece_color);
+                if let Some(target) = self.get_en_passant_target(position) {
+                    captures |= bitboard::square_bit(bitboard::hex_to_square(target));
                 }
+                captures |= pushes & PROMOTION_RANKS;
+                quiets = pushes & !PROMOTION_RANKS;
             }
+            PieceType::King => quiets |= self.get_castling_targets(position),
             _ => {}
         }
 
-        legal_moves
-    }
+        let targets = match kind {
+            MoveKind::All => captures | quiets,
+            MoveKind::Captures => captures,
+            MoveKind::Quiets => quiets,
+        };
 
-    // gets every legal move for every piece on the board
-    pub fn get_board_legal_moves(&self, white_to_move: bool) 
-        -> HashMap<usize, Vec<usize>> {
-            let mut all_moves: HashMap<usize, Vec<usize>> = HashMap::new();
-            if white_to_move {
-                for piece in self.white_set.clone() {
-                    let available_moves = self.get_legal_moves(piece.get_position());
-                    if available_moves.len() > 0 {
-                        all_moves.insert(piece.get_position(), available_moves);
-                    }
+        for target in bitboard::squares(targets) {
+            let target = bitboard::square_to_hex(target);
+            let m = self.create_move(position, target);
+
+            // a pawn reaching the last rank has to pick what it becomes
+            if m.get_piece() == PieceType::Pawn && bitboard::contains(PROMOTION_RANKS,
+                                                                      bitboard::hex_to_square(target)) {
+                for promotion in PROMOTION_PIECES {
+                    moves.push(Move::with_promotion(position, target, m.get_captured(), promotion));
                 }
             } else {
-                for piece in self.black_set.clone() {
-                    let available_moves = self.get_legal_moves(piece.get_position());
-                    if available_moves.len() > 0 {
-                        all_moves.insert(piece.get_position(), available_moves);
-                    }
-                }
+                moves.push(m);
             }
-
-            all_moves
         }
+    }
 
-    // helpers for get_straights and get_diagonals
-    fn add_position_if_valid(&self, list: &mut Vec<usize>, original_file: usize,
-                             original_rank: usize, current_file: usize, current_rank: usize) -> bool {
-        if self.square_empty(current_file, current_rank) {
-            list.push(file_rank_to_hex(current_file, current_rank));
-            true
+    // finds the move given in UCI long algebraic notation (e.g. "e2e4")
+    // among the moves available to the piece it starts from, which has to
+    // belong to the side to move
+    pub fn move_from_uci(&mut self, text: &str) -> Option<Move> {
+        let (from, to, promotion) = moves::parse_uci(text)?;
+
+        self.get_legal_moves(from).into_iter().find(|m| {
+            m.get_to() == to && m.get_promotion() == promotion
+        })
+    }
+
+    // describes moving the piece on `from` to `to`
+    fn create_move(&self, from: usize, to: usize) -> Move {
+        let piece = self.get_piece(from).piece_type;
+        let captured = self.get_piece(to).piece_type;
+        let (from_file, from_rank) = file_rank_from_hex(from);
+        let (to_file, to_rank) = file_rank_from_hex(to);
+
+        if piece == PieceType::Pawn && from_rank.abs_diff(to_rank) == 2 {
+            Move::with_flag(from, to, piece, captured, MoveFlag::DoublePush)
+        } else if piece == PieceType::Pawn && Some(to) == self.en_passant
+            && from_file != to_file {
+            Move::with_flag(from, to, piece, PieceType::Pawn, MoveFlag::EnPassant)
+        } else if piece == PieceType::King && from.abs_diff(to) == 2 {
+            Move::with_flag(from, to, piece, captured, MoveFlag::Castle)
         } else {
-            if self.is_capturable(file_rank_to_hex(original_file, original_rank),
-            file_rank_to_hex(current_file, current_rank)) {
-                list.push(file_rank_to_hex(current_file, current_rank));
-            }
-            false
+            Move::new(from, to, piece, captured)
         }
     }
 
-    pub fn get_straights(&self, rank: usize, file: usize) -> Vec<usize> {
-        let mut straights = Vec::new();
-        let mut current_file = file;
-        let mut current_rank = rank;
+    // squares the pawn on `square` can push to, one step or two from its
+    // starting rank, as long as nothing is in the way
+    fn get_pawn_pushes(&self, square: usize, color: PieceColor) -> Bitboard {
+        let empty = !self.occupied;
+        let pawn = bitboard::square_bit(square);
 
-        // up
-        while current_rank < 7 {
-            current_rank += 1;
-            if !self.add_position_if_valid(&mut straights, file, rank, current_file, current_rank) {
-                break;
+        match color {
+            PieceColor::White => {
+                let single = pawn << 8 & empty;
+                single | (single << 8 & empty & bitboard::RANK_4)
+            }
+            _ => {
+                let single = pawn >> 8 & empty;
+                single | (single >> 8 & empty & bitboard::RANK_5)
             }
         }
-        // down
-        current_file = file;
-        current_rank = rank;
-        while current_rank > 0 {
-            current_rank -= 1;
-            if !self.add_position_if_valid(&mut straights, file, rank, current_file, current_rank) {
-                break;
+    }
+
+    // the en passant square if the pawn on `position` is beside the pawn that
+    // just made a double push and can take it
+    fn get_en_passant_target(&self, position: usize) -> Option<usize> {
+        let target = self.en_passant?;
+        let pawn = self.get_piece(position);
+        let (file, rank) = file_rank_from_hex(position);
+        let (target_file, target_rank) = file_rank_from_hex(target);
+        let forward_rank = match pawn.piece_color {
+            PieceColor::White => rank + 1,
+            _ => rank.wrapping_sub(1),
+        };
+
+        if pawn.piece_color == self.side_to_move && target_rank == forward_rank
+            && target_file.abs_diff(file) == 1 {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    // squares the king on `position` can castle to: the king must be on its
+    // starting square with the right intact, the squares up to the rook empty,
+    // and it may not castle out of, through or into check
+    fn get_castling_targets(&self, position: usize) -> Bitboard {
+        let mut targets = bitboard::EMPTY;
+        let color = self.get_piece(position).piece_color;
+        let (kingside, queenside, king_start) = match color {
+            PieceColor::White => (WHITE_KINGSIDE, WHITE_QUEENSIDE, 0x04),
+            _ => (BLACK_KINGSIDE, BLACK_QUEENSIDE, 0x74),
+        };
+        if position != king_start || self.castling_rights & (kingside | queenside) == 0
+            || self.is_square_attacked(position, color.opposite()) {
+            return targets;
+        }
+
+        let enemy = color.opposite();
+        let empty = |p: usize| self.get_piece(p).piece_type == PieceType::Empty;
+        let safe = |p: usize| !self.is_square_attacked(p, enemy);
+
+        // kingside: the f and g files
+        if self.castling_rights & kingside != 0
+            && empty(position + 1) && empty(position + 2)
+            && safe(position + 1) && safe(position + 2) {
+            targets |= bitboard::square_bit(bitboard::hex_to_square(position + 2));
+        }
+        // queenside: the b, c and d files, though the king never crosses b
+        if self.castling_rights & queenside != 0
+            && empty(position - 1) && empty(position - 2) && empty(position - 3)
+            && safe(position - 1) && safe(position - 2) {
+            targets |= bitboard::square_bit(bitboard::hex_to_square(position - 2));
+        }
+
+        targets
+    }
+
+    // checks the position could be reached in a legal game
+    pub fn validate(&self) -> Result<(), PositionError> {
+        for (color, set) in [(PieceColor::White, &self.white_set.pieces),
+                             (PieceColor::Black, &self.black_set.pieces)] {
+            let count = set.iter().filter(|p| p.piece_type == PieceType::King).count();
+            if count != 1 {
+                return Err(PositionError::KingCount { color, count });
+            }
+            if set.len() > 16 {
+                return Err(PositionError::TooManyPieces { color, count: set.len() });
+            }
+            for piece in set {
+                let (_, rank) = file_rank_from_hex(piece.position);
+                if piece.piece_type == PieceType::Pawn && (rank == 0 || rank == 7) {
+                    return Err(PositionError::PawnOnBackRank(piece.position));
+                }
             }
         }
-        // left
-        current_file = file;
-        current_rank = rank;
-        while current_file > 0 {
-            current_file -= 1;
-            if !self.add_position_if_valid(&mut straights, file, rank, current_file, current_rank) {
-                break;
+
+        // each right needs the king and that rook still on their starting squares
+        for (right, king, rook, color) in [
+            (WHITE_KINGSIDE, 0x04, 0x07, PieceColor::White),
+            (WHITE_QUEENSIDE, 0x04, 0x00, PieceColor::White),
+            (BLACK_KINGSIDE, 0x74, 0x77, PieceColor::Black),
+            (BLACK_QUEENSIDE, 0x74, 0x70, PieceColor::Black),
+        ] {
+            if self.castling_rights & right != 0
+                && (self.get_piece(king).get_type_and_color() != (PieceType::King, color)
+                    || self.get_piece(rook).get_type_and_color() != (PieceType::Rook, color)) {
+                return Err(PositionError::CastlingRights(right));
             }
         }
-        // right
-        current_file = file;
-        current_rank = rank;
-        while current_file < 7 {
-            current_file += 1;
-            if !self.add_position_if_valid(&mut straights, file, rank, current_file, current_rank) {
-                break;
+
+        // the square must have just been skipped by a pawn of the side not to move
+        if let Some(position) = self.en_passant {
+            let (file, rank) = file_rank_from_hex(position);
+            let (expected_rank, pawn_rank, origin_rank) = match self.side_to_move {
+                PieceColor::White => (5, 4, 6),
+                _ => (2, 3, 1),
+            };
+            if rank != expected_rank
+                || !self.square_empty(file, rank)
+                || !self.square_empty(file, origin_rank)
+                || self.get_piece(file_rank_to_hex(file, pawn_rank)).get_type_and_color()
+                    != (PieceType::Pawn, self.side_to_move.opposite()) {
+                return Err(PositionError::EnPassant(position));
             }
         }
 
-        straights
+        if self.is_king_attacked(self.side_to_move.opposite()) {
+            return Err(PositionError::OpponentInCheck);
+        }
+
+        Ok(())
     }
 
-    pub fn get_diagonals(&self, rank: usize, file: usize) -> Vec<usize> {
-        let mut diagonals = Vec::new();
-        let mut current_file = file;
-        let mut current_rank = rank;
+    // checks whether the side to move is in check
+    pub fn in_check(&self) -> bool {
+        self.is_king_attacked(self.side_to_move)
+    }
 
-        // down-left
-        while current_rank > 0 && current_file > 0 {
-            current_file -= 1;
-            current_rank -= 1;
-            if !self.add_position_if_valid(&mut diagonals, file, rank, current_file, current_rank) {
-                break;
-            }
+    // checks whether the king of the given color is attacked
+    fn is_king_attacked(&self, color: PieceColor) -> bool {
+        match self.find_king(color) {
+            Some(king) => self.is_square_attacked(king, color.opposite()),
+            None => false,
         }
+    }
 
-        // down-right
-        current_file = file;
-        current_rank = rank;
-        while current_rank > 0 && current_file < 7 {
-            current_file += 1;
-            current_rank -= 1;
-            if !self.add_position_if_valid(&mut diagonals, file, rank, current_file, current_rank) {
-                break;
-            }
+    // hex position of the king of the given color
+    pub fn find_king(&self, color: PieceColor) -> Option<usize> {
+        if color == PieceColor::None {
+            return None;
         }
 
-        // up-left
-        current_file = file;
-        current_rank = rank;
-        while current_rank < 7 && current_file > 0 {
-            current_file -= 1;
-            current_rank += 1;
-            if !self.add_position_if_valid(&mut diagonals, file, rank, current_file, current_rank) {
-                break;
-            }
+        self.get_piece_list(color).pieces.iter()
+            .find(|p| p.piece_type == PieceType::King)
+            .map(|p| p.position)
+    }
+
+    // checks whether any piece of `by_color` attacks the hex position `square`
+    pub fn is_square_attacked(&self, square: usize, by_color: PieceColor) -> bool {
+        if by_color == PieceColor::None {
+            return false;
         }
 
-        // up-right
-        current_file = file;
-        current_rank = rank;
-        while current_rank < 7 && current_file < 7 {
-            current_file += 1;
-            current_rank += 1;
-            if !self.add_position_if_valid(&mut diagonals, file, rank, current_file, current_rank) {
-                break;
-            }
-        }       
+        let square = bitboard::hex_to_square(square);
+        let pieces = |piece_type| self.get_pieces_bitboard(piece_type, by_color);
+        let queens = pieces(PieceType::Queen);
 
-        diagonals
+        // pawns attack diagonally forward, so look the way the other side's pawns would
+        attacks::pawn_attacks(square, by_color.opposite()) & pieces(PieceType::Pawn) != 0
+            || attacks::knight_attacks(square) & pieces(PieceType::Knight) != 0
+            || attacks::king_attacks(square) & pieces(PieceType::King) != 0
+            || attacks::bishop_attacks(square, self.occupied) & (pieces(PieceType::Bishop) | queens) != 0
+            || attacks::rook_attacks(square, self.occupied) & (pieces(PieceType::Rook) | queens) != 0
     }
 
     fn square_empty(&self, file: usize, rank: usize) -> bool {
         self.get_piece(file_rank_to_hex(file, rank)).piece_type == PieceType::Empty
     }
 
-    pub fn simulate_move(&self, old_position: usize, new_position: usize) -> Board {
-        let mut simulated_board = self.copy();
-        let original_piece = simulated_board.get_piece(old_position);
-        let new_piece = simulated_board.get_piece(new_position);
-
-        // handle capture in white/black set
-        if new_piece.piece_type != PieceType::Empty {
-            match new_piece.piece_color {
-                PieceColor::White => {
-                    for (i, white_piece) in simulated_board.white_set.clone()
-                        .iter().enumerate() {
-                            if white_piece.get_position() == new_position {
-                                simulated_board.white_set.remove(i);
-                            }
-                    }
-                }
-                PieceColor::Black => {
-                    for (i, black_piece) in simulated_board.black_set.clone()
-                        .iter().enumerate() {
-                            if black_piece.get_position() == new_position {
-                                simulated_board.black_set.remove(i);
-                            }
-                        }
-                }
-                PieceColor::None => {},
-            }
+    // plays the move on a copy of the board, leaving this one untouched
+    pub fn simulate_move(&self, m: Move) -> Board {
+        let mut simulated_board = self.clone();
+        simulated_board.make_move(m);
+
+        simulated_board
+    }
+
+    // plays a move in place, returning what unmake_move needs to take it back
+    pub fn make_move(&mut self, m: Move) -> Undo {
+        let old_position = m.get_from();
+        let new_position = m.get_to();
+        let mut undo = Undo {
+            captured: None,
+            side_to_move: self.side_to_move,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+        };
+
+        if m.is_capture() {
+            undo.captured = Some(self.remove_piece(captured_position(m)));
         }
 
-        // move piece
-        simulated_board.set_piece(old_position, 
-                                  DynamicPiece { 
-                                      piece_type: PieceType::Empty, 
-                                      piece_color: PieceColor::None,
-                                      position: old_position 
-                                  });
-        simulated_board.set_piece(new_position, original_piece);
+        self.move_piece(old_position, new_position);
 
-        simulated_board
+        if m.is_promotion() {
+            self.promote_piece(new_position, m.get_promotion());
+        }
+
+        // castling also brings the rook over to the other side of the king
+        if m.get_flag() == MoveFlag::Castle {
+            let (rook_from, rook_to) = castling_rook_positions(new_position);
+            self.move_piece(rook_from, rook_to);
+        }
+
+        // moving the king or a rook, or capturing a rook, gives up the rights tied to it
+        self.castling_rights &= !(castling_rights_lost(old_position)
+                                  | castling_rights_lost(new_position));
+
+        // pawn moves and captures reset the fifty move counter
+        if m.get_piece() == PieceType::Pawn || m.is_capture() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.side_to_move == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+
+        // a double push leaves the square it skipped open to en passant
+        self.en_passant = match m.get_flag() {
+            MoveFlag::DoublePush => Some((old_position + new_position) / 2),
+            _ => None,
+        };
+
+        self.side_to_move = self.side_to_move.opposite();
+
+        self.hash ^= zobrist::castling_key(undo.castling_rights)
+            ^ zobrist::castling_key(self.castling_rights)
+            ^ en_passant_hash(undo.en_passant) ^ en_passant_hash(self.en_passant)
+            ^ zobrist::side_key();
+
+        undo
     }
 
-    // checks if the move is a capture move
-    pub fn is_capturable(&self, old_position: usize, new_position: usize) -> bool {
-        let old_position_piece: DynamicPiece = self.get_piece(old_position);
-        let new_position_piece: DynamicPiece = self.get_piece(new_position);
+    // takes back a move played with make_move, given the Undo it returned
+    pub fn unmake_move(&mut self, m: Move, undo: Undo) {
+        let old_position = m.get_from();
+        let new_position = m.get_to();
 
-        if old_position_piece.piece_color == new_position_piece.piece_color {
-            return false;
+        if m.get_flag() == MoveFlag::Castle {
+            let (rook_from, rook_to) = castling_rook_positions(new_position);
+            self.move_piece(rook_to, rook_from);
         }
 
-        let (old_file, old_rank) = file_rank_from_hex(old_position);
-        let (new_file, new_rank) = file_rank_from_hex(new_position);
+        if m.is_promotion() {
+            self.promote_piece(new_position, PieceType::Pawn);
+        }
 
-        if self.square_empty(new_file, new_rank) {
-            return false;
+        self.move_piece(new_position, old_position);
+
+        if let Some((piece, index)) = undo.captured {
+            self.restore_piece(piece, index);
         }
 
-        match old_position_piece.get_type() {
-            PieceType::Pawn => {
-                if old_position_piece.piece_color == PieceColor::White {
-                    if (old_file > 0 && old_rank < 7 
-                        && new_position == file_rank_to_hex(old_file - 1, old_rank + 1))
-                        || (old_file < 7 && old_rank < 7 && 
-                            new_position == file_rank_to_hex(old_file + 1, old_rank + 1)) {
-                            return true;
-                        }
-                    return false;
-                } else {
-                    if (old_file > 0 && old_rank > 0 
-                        && new_position == file_rank_to_hex(old_file - 1, old_rank - 1))
-                        || (old_file < 7 && old_rank > 0 && 
-                            new_position == file_rank_to_hex(old_file + 1, old_rank - 1)) {
-                            return true;
-                        }
-                    return false;
+        self.side_to_move = undo.side_to_move;
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.hash = undo.hash;
+    }
+
+    fn get_piece_list(&self, color: PieceColor) -> &PieceList {
+        match color {
+            PieceColor::White => &self.white_set,
+            _ => &self.black_set,
+        }
+    }
+
+    fn get_piece_list_mut(&mut self, color: PieceColor) -> &mut PieceList {
+        match color {
+            PieceColor::White => &mut self.white_set,
+            _ => &mut self.black_set,
+        }
+    }
+
+    // put a piece on an empty square and into its white/black set
+    fn add_piece(&mut self, piece: DynamicPiece) {
+        self.squares[piece.position] = piece;
+        self.toggle_bitboards(piece, piece.position);
+        self.get_piece_list_mut(piece.piece_color).add(piece);
+    }
+
+    // take a piece off the board and out of its white/black set,
+    // returning it along with the slot it had in the set
+    fn remove_piece(&mut self, position: usize) -> (DynamicPiece, usize) {
+        let piece = self.get_piece(position);
+        self.squares[position] = empty_square(position);
+        self.toggle_bitboards(piece, position);
+
+        self.get_piece_list_mut(piece.piece_color).remove(position)
+    }
+
+    // put a piece taken off by remove_piece back where it was
+    fn restore_piece(&mut self, piece: DynamicPiece, slot: usize) {
+        self.squares[piece.position] = piece;
+        self.toggle_bitboards(piece, piece.position);
+        self.get_piece_list_mut(piece.piece_color).restore(piece, slot);
+    }
+
+    // turn the pawn on `position` into another piece, on the board and in its set
+    fn promote_piece(&mut self, position: usize, piece_type: PieceType) {
+        let piece = self.squares[position];
+        self.piece_bitboards[piece_type_index(piece.piece_type)] ^= hex_bit(position);
+        self.piece_bitboards[piece_type_index(piece_type)] ^= hex_bit(position);
+        let square = bitboard::hex_to_square(position);
+        self.hash ^= zobrist::piece_key(piece.piece_type, piece.piece_color, square)
+            ^ zobrist::piece_key(piece_type, piece.piece_color, square);
+
+        self.squares[position].piece_type = piece_type;
+        self.get_piece_list_mut(piece.piece_color).set_type(position, piece_type);
+    }
+
+    // relocate a piece onto an empty square, on the board and in its set
+    fn move_piece(&mut self, from: usize, to: usize) {
+        let piece = self.get_piece(from);
+        self.squares[from] = empty_square(from);
+        self.squares[to] = DynamicPiece { position: to, ..piece };
+        self.toggle_bitboards(piece, from);
+        self.toggle_bitboards(piece, to);
+
+        self.get_piece_list_mut(piece.piece_color).relocate(from, to);
+    }
+
+    // flips the bit for `position` in every bitboard the piece belongs to, and
+    // its key for that square in the hash
+    fn toggle_bitboards(&mut self, piece: DynamicPiece, position: usize) {
+        let bit = hex_bit(position);
+        self.piece_bitboards[piece_type_index(piece.piece_type)] ^= bit;
+        self.color_bitboards[color_index(piece.piece_color)] ^= bit;
+        self.occupied ^= bit;
+        self.hash ^= zobrist::piece_key(piece.piece_type, piece.piece_color,
+                                        bitboard::hex_to_square(position));
+    }
+
+    // checks the white/black sets, bitboards and hash match the pieces on the
+    // board, describing the first mismatch found. meant for tests and debugging
+    pub fn check_consistency(&self) -> Result<(), String> {
+        let mut counts = [0, 0];
+
+        for rank in 0..8 {
+            for file in 0..8 {
+                let position = file_rank_to_hex(file, rank);
+                let piece = self.squares[position];
+                let square = hex_to_chess_notation(position);
+                if piece.position != position {
+                    return Err(format!("{} holds a piece recorded at {}", square,
+                                       hex_to_chess_notation(piece.position)));
                 }
-            }
-            PieceType::Rook | PieceType::King | PieceType::Queen | 
-                PieceType::Bishop | PieceType::Knight => {
-                    return true;
+
+                let bit = hex_bit(position);
+                let in_bitboards = match (piece.piece_type, piece.piece_color) {
+                    (PieceType::Empty, _) | (_, PieceColor::None) => self.occupied & bit == 0,
+                    (piece_type, color) => self.get_pieces_bitboard(piece_type, color) & bit != 0
+                        && self.occupied & bit != 0,
+                };
+                if !in_bitboards {
+                    return Err(format!("{} doesn't match the bitboards", square));
+                }
+
+                let (list, count) = match (piece.piece_type, piece.piece_color) {
+                    (PieceType::Empty, PieceColor::None) => continue,
+                    (PieceType::Empty, _) | (_, PieceColor::None) => {
+                        return Err(format!("{} holds a piece without a type or color", square));
+                    }
+                    (_, PieceColor::White) => (&self.white_set, &mut counts[0]),
+                    (_, PieceColor::Black) => (&self.black_set, &mut counts[1]),
+                };
+                if list.get(position) != Some(&piece) {
+                    return Err(format!("{} holds a piece missing from its set", square));
                 }
-            _ => {false}
+                *count += 1;
+            }
+        }
+
+        // the per type and per color bitboards must agree with each other
+        let by_type = self.piece_bitboards.iter().fold(bitboard::EMPTY, |all, b| all | b);
+        let by_color = self.color_bitboards[0] | self.color_bitboards[1];
+        if by_type != self.occupied || by_color != self.occupied
+            || self.color_bitboards[0] & self.color_bitboards[1] != bitboard::EMPTY {
+            return Err("bitboards disagree with the occupancy".to_string());
+        }
+
+        // every piece on the board was found in its set, so any extra entry is stale
+        if counts != [self.white_set.pieces.len(), self.black_set.pieces.len()] {
+            return Err(format!("sets hold {} white and {} black pieces, board has {:?}",
+                               self.white_set.pieces.len(), self.black_set.pieces.len(), counts));
+        }
+
+        if self.hash != self.compute_hash() {
+            return Err("hash doesn't match the position".to_string());
         }
+
+        Ok(())
     }
+}
+
 
+// parse castling rights from FEN, e.g. "KQkq", "Kq" or "-"
+fn parse_castling_rights(field: &str) -> Result<u8, FenError> {
+    if field == "-" {
+        return Ok(0);
+    }
+
+    let mut rights = 0;
+    for c in field.chars() {
+        let right = match c {
+            'K' => WHITE_KINGSIDE,
+            'Q' => WHITE_QUEENSIDE,
+            'k' => BLACK_KINGSIDE,
+            'q' => BLACK_QUEENSIDE,
+            _ => 0,
+        };
+        // unknown letters and repeated rights are both malformed
+        if right == 0 || rights & right != 0 {
+            return Err(FenError::InvalidCastling(field.to_string()));
+        }
+        rights |= right;
+    }
+
+    Ok(rights)
 }
 
+fn piece_from_fen_char(c: char) -> Option<(PieceType, PieceColor)> {
+    let piece_type = match c.to_ascii_lowercase() {
+        'p' => PieceType::Pawn,
+        'n' => PieceType::Knight,
+        'b' => PieceType::Bishop,
+        'r' => PieceType::Rook,
+        'q' => PieceType::Queen,
+        'k' => PieceType::King,
+        _ => return None,
+    };
+    let piece_color = if c.is_ascii_uppercase() { PieceColor::White } else { PieceColor::Black };
+
+    Some((piece_type, piece_color))
+}
 
 fn get_piece_symbol(piece_type: PieceType, piece_color: PieceColor) -> String {
     match piece_color {
@@ -888,6 +1273,79 @@ fn file_to_chess_notation(position: usize) -> String {
     }
 }
 
+// index of a piece type into the per type bitboards
+fn piece_type_index(piece_type: PieceType) -> usize {
+    match piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+        PieceType::Empty => panic!("Empty squares have no bitboard"),
+    }
+}
+
+// index of a color into the per color bitboards
+fn color_index(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+        PieceColor::None => panic!("Empty squares have no bitboard"),
+    }
+}
+
+// the bitboard holding only the square at a hex position
+fn hex_bit(position: usize) -> Bitboard {
+    bitboard::square_bit(bitboard::hex_to_square(position))
+}
+
+fn empty_square(position: usize) -> DynamicPiece {
+    DynamicPiece { piece_type: PieceType::Empty, piece_color: PieceColor::None, position }
+}
+
+// the part of the hash for an en passant square, which only depends on its file
+fn en_passant_hash(en_passant: Option<usize>) -> u64 {
+    en_passant.map_or(0, |position| zobrist::en_passant_key(position & 0x7))
+}
+
+// hex position of the piece a capture takes, the pawn taken en passant
+// sits beside the moving pawn rather than on its target
+fn captured_position(m: Move) -> usize {
+    match m.get_flag() {
+        MoveFlag::EnPassant => {
+            let (new_file, _) = file_rank_from_hex(m.get_to());
+            let (_, old_rank) = file_rank_from_hex(m.get_from());
+            file_rank_to_hex(new_file, old_rank)
+        }
+        _ => m.get_to(),
+    }
+}
+
+// the rook's (from, to) hex positions when the king castles to `king_target`
+fn castling_rook_positions(king_target: usize) -> (usize, usize) {
+    match king_target {
+        0x06 => (0x07, 0x05),
+        0x02 => (0x00, 0x03),
+        0x76 => (0x77, 0x75),
+        0x72 => (0x70, 0x73),
+        _ => panic!("Invalid castling target"),
+    }
+}
+
+// castling rights that no longer hold once a piece leaves or is captured on `position`
+fn castling_rights_lost(position: usize) -> u8 {
+    match position {
+        0x04 => WHITE_KINGSIDE | WHITE_QUEENSIDE,
+        0x07 => WHITE_KINGSIDE,
+        0x00 => WHITE_QUEENSIDE,
+        0x74 => BLACK_KINGSIDE | BLACK_QUEENSIDE,
+        0x77 => BLACK_KINGSIDE,
+        0x70 => BLACK_QUEENSIDE,
+        _ => 0,
+    }
+}
+
 // gets the (file, rank) tuple from hex position
 pub fn file_rank_from_hex(position: usize) -> (usize, usize) {
     let file = position & 0xF;
@@ -899,3 +1357,454 @@ pub fn file_rank_from_hex(position: usize) -> (usize, usize) {
 pub fn file_rank_to_hex(file: usize, rank: usize) -> usize {
     rank * 16 + file
 }
+
+// gets the hex position from a square in chess notation (e.g. "e4")
+pub fn chess_notation_to_hex(notation: &str) -> Option<usize> {
+    let mut chars = notation.chars();
+    let file = match chars.next()? {
+        c @ 'a'..='h' => c as usize - 'a' as usize,
+        _ => return None,
+    };
+    let rank = match chars.next()? {
+        c @ '1'..='8' => c as usize - '1' as usize,
+        _ => return None,
+    };
+    if chars.next().is_some() {
+        return None;
+    }
+
+    Some(file_rank_to_hex(file, rank))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the error from_fen gives for a FEN it should reject
+    fn fen_error(fen: &str) -> FenError {
+        Board::from_fen(fen).err().expect("FEN should be rejected")
+    }
+
+    #[test]
+    fn fen_placement_errors() {
+        assert_eq!(fen_error("8/8/8/8/8/8/8/8/8 w - - 0 1"), FenError::RankCount(9));
+        assert_eq!(fen_error("4k3/8/8/8 w - - 0 1"), FenError::RankCount(4));
+        assert_eq!(fen_error("rnbqkbnrr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+                   FenError::FileCount { rank: 8, files: 9 });
+        assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K31 w - - 0 1"),
+                   FenError::FileCount { rank: 1, files: 9 });
+        assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K2 w - - 0 1"),
+                   FenError::FileCount { rank: 1, files: 7 });
+        assert_eq!(fen_error("4k3/9/8/8/8/8/8/4K3 w - - 0 1"), FenError::InvalidPiece('9'));
+        assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K2\u{2654} w - - 0 1"),
+                   FenError::InvalidPiece('\u{2654}'));
+        assert_eq!(fen_error("4k3/8/8/3x4/8/8/8/4K3 w - - 0 1"), FenError::InvalidPiece('x'));
+    }
+
+    #[test]
+    fn fen_field_errors() {
+        let placement = "r3k2r/8/8/8/8/8/8/R3K2R";
+        let error = |rest: &str| fen_error(&format!("{} {}", placement, rest));
+
+        assert_eq!(error("x KQkq - 0 1"), FenError::InvalidSideToMove("x".to_string()));
+        assert_eq!(error("white KQkq - 0 1"), FenError::InvalidSideToMove("white".to_string()));
+        assert_eq!(error("w KQkx - 0 1"), FenError::InvalidCastling("KQkx".to_string()));
+        assert_eq!(error("w KK - 0 1"), FenError::InvalidCastling("KK".to_string()));
+        assert_eq!(error("w KQkq e4 0 1"), FenError::InvalidEnPassant("e4".to_string()));
+        assert_eq!(error("w KQkq i6 0 1"), FenError::InvalidEnPassant("i6".to_string()));
+        assert_eq!(error("w KQkq e66 0 1"), FenError::InvalidEnPassant("e66".to_string()));
+        assert_eq!(error("w KQkq - -1 1"), FenError::InvalidHalfmoveClock("-1".to_string()));
+        assert_eq!(error("w KQkq - 99999999999999999999999 1"),
+                   FenError::InvalidHalfmoveClock("99999999999999999999999".to_string()));
+        assert_eq!(error("w KQkq - 0 0"), FenError::InvalidFullmoveNumber("0".to_string()));
+        assert_eq!(error("w KQkq - 0 one"), FenError::InvalidFullmoveNumber("one".to_string()));
+        assert_eq!(error("w KQkq - 0 1 extra"), FenError::TooManyFields);
+    }
+
+    #[test]
+    fn fen_missing_fields() {
+        assert_eq!(fen_error(""), FenError::MissingField(FenField::Placement));
+        assert_eq!(fen_error("   "), FenError::MissingField(FenField::Placement));
+        assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K3"), FenError::MissingField(FenField::SideToMove));
+        assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K3 w"), FenError::MissingField(FenField::Castling));
+        assert_eq!(fen_error("4k3/8/8/8/8/8/8/4K3 w -"),
+                   FenError::MissingField(FenField::EnPassant));
+
+        // the clocks are optional, as in EPD
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(board.get_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    // the reason validate rejects the position in a well formed FEN
+    fn position_error(fen: &str) -> PositionError {
+        let board = Board::parse_fen(fen).unwrap();
+        let error = board.validate().expect_err("position should be rejected");
+        assert_eq!(fen_error(fen), FenError::IllegalPosition(error));
+        error
+    }
+
+    #[test]
+    fn validate_accepts_reachable_positions() {
+        assert_eq!(Board::new().validate(), Ok(()));
+        let board = Board::parse_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3");
+        assert_eq!(board.unwrap().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_counts_kings_and_pieces() {
+        assert_eq!(position_error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+                   PositionError::KingCount { color: PieceColor::Black, count: 0 });
+        assert_eq!(position_error("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+                   PositionError::KingCount { color: PieceColor::White, count: 2 });
+        assert_eq!(position_error("4k3/8/8/8/8/NNNNNNNN/PPPPPPPP/4K3 w - - 0 1"),
+                   PositionError::TooManyPieces { color: PieceColor::White, count: 17 });
+        assert_eq!(position_error("4k3/8/8/8/8/8/8/p3K3 w - - 0 1"),
+                   PositionError::PawnOnBackRank(0x00));
+        assert_eq!(position_error("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+                   PositionError::PawnOnBackRank(0x70));
+    }
+
+    #[test]
+    fn validate_castling_en_passant_and_check() {
+        // the h8 rook is missing, then the white king has left e1
+        assert_eq!(position_error("r3k3/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+                   PositionError::CastlingRights(BLACK_KINGSIDE));
+        assert_eq!(position_error("r3k2r/8/8/8/8/8/8/R4K1R w Qk - 0 1"),
+                   PositionError::CastlingRights(WHITE_QUEENSIDE));
+
+        // no pawn in front of the square, and a square black would have skipped with white to move
+        assert_eq!(position_error("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"),
+                   PositionError::EnPassant(0x54));
+        assert_eq!(position_error("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"),
+                   PositionError::EnPassant(0x24));
+
+        assert_eq!(position_error("4k2R/8/8/8/8/8/8/4K3 w - - 0 1"),
+                   PositionError::OpponentInCheck);
+    }
+
+    // the legal moves of the side to move the filter keeps, in UCI notation
+    fn legal_moves(fen: &str, filter: impl Fn(&Move) -> bool) -> Vec<String> {
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut moves: Vec<String> = board.get_board_legal_moves().iter()
+            .filter(|m| filter(m))
+            .map(|m| m.to_string())
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    // the legal moves of the piece on `position`
+    fn moves_from(fen: &str, position: usize) -> Vec<String> {
+        legal_moves(fen, |m| m.get_from() == position)
+    }
+
+    #[test]
+    fn pinned_pieces_stay_on_the_pin() {
+        // the knight can't leave the e file, the rook can only slide along it
+        assert!(moves_from("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1", 0x14).is_empty());
+        assert_eq!(moves_from("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1", 0x14),
+                   vec!["e2e3", "e2e4", "e2e5", "e2e6", "e2e7"]);
+        // pinned along a diagonal, by a bishop
+        assert!(moves_from("4k3/8/8/b7/8/8/3P4/4K3 w - - 0 1", 0x13).is_empty());
+        assert!(!Board::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap().in_check());
+    }
+
+    #[test]
+    fn pieces_block_or_capture_a_check() {
+        let fen = "4k3/4r3/8/8/8/5N2/8/4K3 w - - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        assert!(board.in_check());
+        assert!(board.is_square_attacked(0x04, PieceColor::Black));
+        // only the knight move onto the e file helps
+        assert_eq!(legal_moves(fen, |m| m.get_piece() != PieceType::King), vec!["f3e5"]);
+
+        // the bishop can take the rook, the pawn can't help
+        let fen = "4k3/4r3/8/8/1B6/8/3P4/4K3 w - - 0 1";
+        assert_eq!(moves_from(fen, 0x31), vec!["b4e7"]);
+        assert!(moves_from(fen, 0x13).is_empty());
+    }
+
+    // the castling moves open to the side to move
+    fn castles(fen: &str) -> Vec<String> {
+        legal_moves(fen, |m| m.get_flag() == MoveFlag::Castle)
+    }
+
+    // plays the moves from the FEN and gives back the resulting FEN
+    fn play(fen: &str, moves: &[&str]) -> String {
+        let mut board = Board::from_fen(fen).unwrap();
+        for text in moves {
+            let m = board.move_from_uci(text).unwrap_or_else(|| panic!("{} is legal", text));
+            board.make_move(m);
+        }
+        board.get_fen()
+    }
+
+    #[test]
+    fn castling_needs_safe_empty_squares() {
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), vec!["e1c1", "e1g1"]);
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"), vec!["e8c8", "e8g8"]);
+        // out of check
+        assert!(castles("4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1").is_empty());
+        // through an attacked f1 or d1, and into an attacked g1
+        assert_eq!(castles("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1"), vec!["e1c1"]);
+        assert_eq!(castles("4k3/8/8/8/8/8/3r4/R3K2R w KQ - 0 1"), vec!["e1g1"]);
+        assert_eq!(castles("4k3/8/8/8/8/8/6r1/R3K2R w KQ - 0 1"), vec!["e1c1"]);
+        // the king never crosses b1, so an attack there doesn't matter, a piece does
+        assert_eq!(castles("4k3/8/8/8/8/8/1r6/R3K2R w KQ - 0 1"), vec!["e1c1", "e1g1"]);
+        assert_eq!(castles("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1"), vec!["e1g1"]);
+        // no right, no castling
+        assert_eq!(castles("4k3/8/8/8/8/8/8/R3K2R w K - 0 1"), vec!["e1g1"]);
+    }
+
+    #[test]
+    fn moving_king_or_rook_drops_rights() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(play(fen, &["e1f1"]), "r3k2r/8/8/8/8/8/8/R4K1R b kq - 1 1");
+        assert_eq!(play(fen, &["a1b1"]), "r3k2r/8/8/8/8/8/8/1R2K2R b Kkq - 1 1");
+        assert_eq!(play(fen, &["h1g1", "h8g8"]), "r3k1r1/8/8/8/8/8/8/R3K1R1 w Qq - 2 2");
+        // moving the rook back doesn't bring the right back
+        assert_eq!(play(fen, &["h1g1", "a8b8", "g1h1"]), "1r2k2r/8/8/8/8/8/8/R3K2R b Qk - 3 2");
+
+        assert_eq!(castling_rights_lost(0x04), WHITE_KINGSIDE | WHITE_QUEENSIDE);
+        assert_eq!(castling_rights_lost(0x77), BLACK_KINGSIDE);
+        assert_eq!(castling_rights_lost(0x14), 0);
+    }
+
+    #[test]
+    fn capturing_a_rook_drops_its_right() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        // both sides lose the queenside right on the a file
+        assert_eq!(play(fen, &["a1a8"]), "R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1");
+        assert_eq!(play("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1", &["g2h1"]),
+                   "r3k2r/8/8/8/8/8/8/R3K2b w Qkq - 0 2");
+    }
+
+    #[test]
+    fn castling_round_trips_through_fen() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(Board::from_fen(fen).unwrap().get_fen(), fen);
+
+        let after = play(fen, &["e1g1"]);
+        assert_eq!(after, "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+        assert_eq!(Board::from_fen(&after).unwrap().get_fen(), after);
+
+        let after = play(fen, &["e1g1", "e8c8"]);
+        assert_eq!(after, "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+        let board = Board::from_fen(&after).unwrap();
+        assert_eq!(board.get_castling_rights(), 0);
+        assert_eq!(board.get_fen(), after);
+    }
+
+    // the legal capturing moves of the king of the side to move
+    fn king_captures(fen: &str) -> Vec<String> {
+        legal_moves(fen, |m| m.get_piece() == PieceType::King && m.is_capture())
+    }
+
+    #[test]
+    fn king_captures_undefended_piece() {
+        assert_eq!(king_captures("4k3/8/8/8/8/8/4n3/4K3 w - - 0 1"), vec!["e1e2"]);
+    }
+
+    #[test]
+    fn king_captures_in_every_direction() {
+        for (fen, capture) in [("4k3/8/8/3n4/4K3/8/8/8 w - - 0 1", "e4d5"),
+                               ("4k3/8/8/4n3/4K3/8/8/8 w - - 0 1", "e4e5"),
+                               ("4k3/8/8/5n2/4K3/8/8/8 w - - 0 1", "e4f5"),
+                               ("4k3/8/8/8/3nK3/8/8/8 w - - 0 1", "e4d4"),
+                               ("4k3/8/8/8/4Kn2/8/8/8 w - - 0 1", "e4f4"),
+                               ("4k3/8/8/8/4K3/3n4/8/8 w - - 0 1", "e4d3"),
+                               ("4k3/8/8/8/4K3/4n3/8/8 w - - 0 1", "e4e3"),
+                               ("4k3/8/8/8/4K3/5n2/8/8 w - - 0 1", "e4f3")] {
+            assert_eq!(king_captures(fen), vec![capture], "{}", fen);
+        }
+    }
+
+    #[test]
+    fn black_king_captures() {
+        assert_eq!(king_captures("4k3/3P4/8/8/8/8/8/4K3 b - - 0 1"), vec!["e8d7"]);
+    }
+
+    #[test]
+    fn king_escapes_check_by_capturing_checker() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1").unwrap();
+        assert!(board.in_check());
+
+        let capture = board.get_legal_moves(0x04).into_iter()
+            .find(|m| m.get_to() == 0x13)
+            .expect("king can take the checking queen");
+        assert_eq!(capture.get_captured(), PieceType::Queen);
+    }
+
+    #[test]
+    fn king_cannot_capture_defended_piece() {
+        assert!(king_captures("3rk3/8/8/8/8/8/3r4/4K3 w - - 0 1").is_empty());
+        // defended by the enemy king
+        assert!(king_captures("8/8/8/8/8/3k4/3p4/3K4 w - - 0 1").is_empty());
+    }
+
+    #[test]
+    fn en_passant_removes_captured_pawn() {
+        let mut board = Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let m = board.move_from_uci("e5d6").expect("en passant is generated");
+        assert_eq!(m.get_flag(), MoveFlag::EnPassant);
+        assert_eq!(m.get_captured(), PieceType::Pawn);
+
+        let after = board.simulate_move(m);
+        assert_eq!(after.get_fen(), "4k3/8/3P4/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(after.get_black_pieces().len(), 1);
+    }
+
+    #[test]
+    fn en_passant_square_set_after_double_push() {
+        let mut board = Board::new();
+        let m = board.move_from_uci("e2e4").unwrap();
+        let after = board.simulate_move(m);
+        assert_eq!(after.get_en_passant(), Some(0x24));
+        assert_eq!(after.get_fen(),
+                   "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+    }
+
+    #[test]
+    fn en_passant_horizontal_pin() {
+        // taking would clear both pawns off the 5th rank and expose the king to the rook
+        let mut board = Board::from_fen("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1").unwrap();
+        assert!(board.move_from_uci("e5d6").is_none());
+        assert!(board.move_from_uci("e5e6").is_some());
+    }
+
+    #[test]
+    fn promotions_to_every_piece() {
+        let mut board = Board::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut moves: Vec<String> = board.get_legal_moves(0x60).iter()
+            .map(|m| m.to_string())
+            .collect();
+        moves.sort();
+        assert_eq!(moves, vec!["a7a8b", "a7a8n", "a7a8q", "a7a8r",
+                               "a7b8b", "a7b8n", "a7b8q", "a7b8r"]);
+    }
+
+    #[test]
+    fn promotion_replaces_pawn() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/p7/1R2K3 b - - 0 1").unwrap();
+        let m = board.move_from_uci("a2b1n").unwrap();
+        let after = board.simulate_move(m);
+        assert_eq!(after.get_fen(), "4k3/8/8/8/8/8/8/1n2K3 w - - 0 2");
+        assert!(after.get_black_pieces().iter()
+                .any(|p| p.get_type_and_color() == (PieceType::Knight, PieceColor::Black)
+                     && p.get_position() == 0x01));
+        assert_eq!(after.get_white_pieces().len(), 1);
+    }
+
+    #[test]
+    fn unmake_move_restores_position() {
+        // castling, en passant, promotions and captures all show up two plies deep
+        let mut board = Board::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let fen = board.get_fen();
+        let white_pieces = board.get_white_pieces();
+        let black_pieces = board.get_black_pieces();
+
+        for m in board.get_board_legal_moves() {
+            let undo = board.make_move(m);
+            for reply in board.get_board_legal_moves() {
+                let reply_undo = board.make_move(reply);
+                assert_eq!(board.check_consistency(), Ok(()), "after {} {}", m, reply);
+                board.unmake_move(reply, reply_undo);
+            }
+            board.unmake_move(m, undo);
+
+            assert_eq!(board.get_fen(), fen, "after {}", m);
+            assert!(board.get_white_pieces() == white_pieces, "after {}", m);
+            assert!(board.get_black_pieces() == black_pieces, "after {}", m);
+        }
+    }
+
+    #[test]
+    fn captures_and_quiets_split_all_moves() {
+        // white can capture, promote by capturing or pushing, and castle
+        let board = Board::from_fen("r3k2r/1P4b1/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let mut all = MoveList::new();
+        let mut captures = MoveList::new();
+        let mut quiets = MoveList::new();
+        board.generate_moves(&mut all);
+        board.generate_captures(&mut captures);
+        board.generate_quiets(&mut quiets);
+
+        assert_eq!(captures.len() + quiets.len(), all.len());
+        assert!(captures.iter().all(|m| m.is_capture() || m.is_promotion()));
+        assert!(quiets.iter().all(|m| !m.is_capture() && !m.is_promotion()));
+        assert!(all.iter().all(|m| captures.contains(m) || quiets.contains(m)));
+        // b7xa8 and b8 promotions, exd6 en passant, and both rooks taking rooks
+        assert_eq!(captures.len(), 8 + 1 + 2);
+        assert!(quiets.iter().any(|m| m.get_flag() == MoveFlag::Castle));
+    }
+
+    #[test]
+    fn hash_depends_on_position_not_move_order() {
+        let mut board = Board::new();
+        let start = board.get_hash();
+        for text in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            let m = board.move_from_uci(text).unwrap();
+            board.make_move(m);
+            assert_eq!(board.get_hash(), board.compute_hash(), "after {}", text);
+        }
+        assert_eq!(board.get_hash(), start);
+
+        // the same pieces with en passant open, castling gone or black to move all differ
+        let fens = ["rnbqkbnr/pppp1ppp/8/4pP2/8/8/PPPPP1PP/RNBQKBNR w KQkq e6 0 3",
+                    "rnbqkbnr/pppp1ppp/8/4pP2/8/8/PPPPP1PP/RNBQKBNR w KQkq - 0 3",
+                    "rnbqkbnr/pppp1ppp/8/4pP2/8/8/PPPPP1PP/RNBQKBNR w Kkq - 0 3",
+                    "rnbqkbnr/pppp1ppp/8/4pP2/8/8/PPPPP1PP/RNBQKBNR b KQkq - 0 3"];
+        let hashes: Vec<u64> = fens.iter().map(|fen| Board::from_fen(fen).unwrap().get_hash()).collect();
+        for i in 0..hashes.len() {
+            for j in i + 1..hashes.len() {
+                assert_ne!(hashes[i], hashes[j], "{} and {}", fens[i], fens[j]);
+            }
+        }
+    }
+
+    #[test]
+    fn set_piece_keeps_sets_in_sync() {
+        let mut board = Board::new();
+        let knight = board.get_piece(0x06);
+
+        // replace a pawn, then clear the knight's square
+        board.set_piece(0x14, knight);
+        board.set_piece(0x06, empty_square(0x06));
+        assert_eq!(board.check_consistency(), Ok(()));
+        assert_eq!(board.get_white_pieces().len(), 15);
+        assert!(board.get_white_pieces().iter().any(|p| p.get_position() == 0x14
+                                                    && p.get_type() == PieceType::Knight));
+    }
+
+    #[test]
+    fn check_consistency_reports_stale_sets() {
+        let mut board = Board::new();
+        board.squares[0x14] = empty_square(0x14);
+        assert!(board.check_consistency().is_err());
+    }
+
+    #[test]
+    fn only_the_side_to_move_can_move() {
+        let mut board = Board::new();
+        assert!(board.move_from_uci("e7e5").is_none());
+        assert!(board.get_legal_moves(0x64).is_empty());
+
+        let m = board.move_from_uci("e2e4").unwrap();
+        board.make_move(m);
+        assert_eq!(board.get_side_to_move(), PieceColor::Black);
+        assert!(board.move_from_uci("d2d4").is_none());
+        assert!(board.move_from_uci("e7e5").is_some());
+        assert_eq!(board.get_hash(), board.compute_hash());
+    }
+
+    #[test]
+    fn king_cannot_capture_own_pieces() {
+        let mut board = Board::new();
+        assert!(board.get_legal_moves(0x04).is_empty());
+        // with black to move, so the black king's moves are actually generated
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert!(board.get_legal_moves(0x74).is_empty());
+    }
+}
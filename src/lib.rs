@@ -0,0 +1,9 @@
+pub mod attacks;
+pub mod bitboard;
+pub mod board;
+pub mod moves;
+pub mod perft;
+pub mod search;
+pub mod time_manager;
+pub mod uci;
+pub mod zobrist;
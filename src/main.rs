@@ -1,35 +1,39 @@
-use std::time::Instant;
+use std::env;
+use std::process;
 
-mod board;
-mod search;
+use chess::board::{Board, START_FEN};
+use chess::perft;
 
 fn main() {
-    let board = &mut board::Board::new();
-    let mut position_counter = PositionCounter::new();
-    let mut line = String::new();
-    // println!("\nPlease input a depth: ");
-    //std::io::stdin().read_line(&mut line).expect("failed to read from stdin");
-    // let depth = match line.trim().parse::<usize>() {
-        // Ok(i) => i,
-        // Err(_) => {println!("Invalid depth"); 0},
-    // };
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let start = Instant::now();
-    println!("{}", search::search(board, 4, true, &mut position_counter));
-
-    println!("Elapsed time: {:.2?}", start.elapsed());
+    match args.first().map(String::as_str) {
+        Some("perft") => run_perft(&args[1..]),
+        _ => chess::uci::run(),
+    }
 }
 
-pub struct PositionCounter {
-    positions: usize,
-}
+// chess perft <depth> [--fen <fen>]
+fn run_perft(args: &[String]) {
+    let depth = match args.first().and_then(|depth| depth.parse().ok()) {
+        Some(depth) => depth,
+        None => exit_with("usage: chess perft <depth> [--fen <fen>]"),
+    };
 
-impl PositionCounter {
-    pub fn new() -> Self {
-        PositionCounter { positions: 0 }
-    }
+    // the FEN can be passed quoted or as separate words
+    let fen = match args.iter().position(|arg| arg == "--fen") {
+        Some(i) => args[i + 1..].join(" "),
+        None => START_FEN.to_string(),
+    };
+    let mut board = match Board::from_fen(&fen) {
+        Ok(board) => board,
+        Err(error) => exit_with(&format!("invalid fen: {}", error)),
+    };
 
-    pub fn add(&mut self) {
-        self.positions += 1;
-    }
+    perft::print_divide(&mut board, depth);
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
 }
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::board::{self, PieceType};
+
+// special rules a move follows besides relocating a piece
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MoveFlag {
+    None,
+    DoublePush,
+    EnPassant,
+    Castle,
+}
+
+// a single move, positions are 0x88 indices stored as bytes to keep it small
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Move {
+    from: u8,
+    to: u8,
+    piece: PieceType,
+    captured: PieceType,
+    promotion: PieceType,
+    flag: MoveFlag,
+}
+
+impl Move {
+    pub fn new(from: usize, to: usize, piece: PieceType, captured: PieceType) -> Self {
+        Move {
+            from: from as u8,
+            to: to as u8,
+            piece,
+            captured,
+            promotion: PieceType::Empty,
+            flag: MoveFlag::None,
+        }
+    }
+
+    // same as `new` but for moves with special rules
+    pub fn with_flag(from: usize, to: usize, piece: PieceType, captured: PieceType,
+                     flag: MoveFlag) -> Self {
+        Move { flag, ..Move::new(from, to, piece, captured) }
+    }
+
+    // a pawn move onto the last rank turning into `promotion`
+    pub fn with_promotion(from: usize, to: usize, captured: PieceType,
+                          promotion: PieceType) -> Self {
+        Move { promotion, ..Move::new(from, to, PieceType::Pawn, captured) }
+    }
+
+    pub fn get_from(&self) -> usize {
+        self.from as usize
+    }
+
+    pub fn get_to(&self) -> usize {
+        self.to as usize
+    }
+
+    pub fn get_piece(&self) -> PieceType {
+        self.piece
+    }
+
+    // type of the piece taken by this move, `Empty` if it isn't a capture
+    pub fn get_captured(&self) -> PieceType {
+        self.captured
+    }
+
+    // type the pawn turns into, `Empty` if it isn't a promotion
+    pub fn get_promotion(&self) -> PieceType {
+        self.promotion
+    }
+
+    pub fn get_flag(&self) -> MoveFlag {
+        self.flag
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.captured != PieceType::Empty
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        self.promotion != PieceType::Empty
+    }
+}
+
+// prints the move in UCI long algebraic notation (e.g. "e2e4", "e7e8q")
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", board::hex_to_chess_notation(self.get_from()),
+               board::hex_to_chess_notation(self.get_to()))?;
+        match self.promotion {
+            PieceType::Queen => write!(f, "q"),
+            PieceType::Rook => write!(f, "r"),
+            PieceType::Bishop => write!(f, "b"),
+            PieceType::Knight => write!(f, "n"),
+            _ => Ok(()),
+        }
+    }
+}
+
+// splits a move in UCI long algebraic notation into its from and to positions
+// and promotion piece, without checking it against any position
+pub fn parse_uci(text: &str) -> Option<(usize, usize, PieceType)> {
+    let from = board::chess_notation_to_hex(text.get(0..2)?)?;
+    let to = board::chess_notation_to_hex(text.get(2..4)?)?;
+    let promotion = match text.get(4..)? {
+        "" => PieceType::Empty,
+        "q" => PieceType::Queen,
+        "r" => PieceType::Rook,
+        "b" => PieceType::Bishop,
+        "n" => PieceType::Knight,
+        _ => return None,
+    };
+
+    Some((from, to, promotion))
+}
+
+// more than the most moves any reachable position has, so a list never overflows
+pub const MAX_MOVES: usize = 256;
+
+// fills the unused slots of a move list
+const NULL_MOVE: Move = Move {
+    from: 0,
+    to: 0,
+    piece: PieceType::Empty,
+    captured: PieceType::Empty,
+    promotion: PieceType::Empty,
+    flag: MoveFlag::None,
+};
+
+// moves generated for a position, kept on the stack so generating them
+// doesn't allocate. they stay in the order they were added
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList { moves: [NULL_MOVE; MAX_MOVES], len: 0 }
+    }
+
+    pub fn push(&mut self, m: Move) {
+        self.moves[self.len] = m;
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<Move> {
+        self.as_slice().get(index).copied()
+    }
+
+    pub fn as_slice(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Move> + '_ {
+        self.as_slice().iter().copied()
+    }
+
+    pub fn contains(&self, m: Move) -> bool {
+        self.as_slice().contains(&m)
+    }
+
+    // orders the moves by the key, keeping the generated order among equal keys
+    pub fn sort_by_key<K: Ord>(&mut self, key: impl FnMut(&Move) -> K) {
+        self.moves[..self.len].sort_by_key(key);
+    }
+
+    // keeps only the moves `keep` accepts, preserving their order
+    pub fn retain(&mut self, mut keep: impl FnMut(Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Copied<std::slice::Iter<'a, Move>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uci_round_trip() {
+        assert_eq!(parse_uci("e2e4"), Some((0x14, 0x34, PieceType::Empty)));
+        assert_eq!(Move::new(0x14, 0x34, PieceType::Pawn, PieceType::Empty).to_string(), "e2e4");
+
+        assert_eq!(parse_uci("e7e8q"), Some((0x64, 0x74, PieceType::Queen)));
+        assert_eq!(parse_uci("a2a1n"), Some((0x10, 0x00, PieceType::Knight)));
+        let promotion = Move::with_promotion(0x64, 0x74, PieceType::Empty, PieceType::Queen);
+        assert_eq!(promotion.to_string(), "e7e8q");
+    }
+
+    #[test]
+    fn uci_rejects_malformed_moves() {
+        for text in ["e2e4x", "e2", "e9e4", "", "e2e", "i2e4", "e2e4qq", "e7e8k", "E2E4", "é2e4"] {
+            assert_eq!(parse_uci(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::board::Board;
+use crate::moves::Move;
+
+// counts the leaf positions `depth` plies below the board, the standard way
+// to check move generation against the numbers other engines report
+pub fn perft(board: &mut Board, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = board.get_board_legal_moves();
+    // every legal move is a leaf one ply from the end, no need to play them
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for m in moves {
+        let undo = board.make_move(m);
+        nodes += perft(board, depth - 1);
+        board.unmake_move(m, undo);
+    }
+
+    nodes
+}
+
+// perft split by root move, to narrow a wrong total down to the move under
+// which the generator goes astray
+pub fn divide(board: &mut Board, depth: usize) -> Vec<(Move, u64)> {
+    let mut counts = Vec::new();
+    if depth == 0 {
+        return counts;
+    }
+
+    for m in board.get_board_legal_moves() {
+        let undo = board.make_move(m);
+        counts.push((m, perft(board, depth - 1)));
+        board.unmake_move(m, undo);
+    }
+
+    counts
+}
+
+// prints the divide counts and their total the way UCI engines answer `go perft`
+pub fn print_divide(board: &mut Board, depth: usize) {
+    let counts = divide(board, depth);
+    for (m, nodes) in &counts {
+        println!("{}: {}", m, nodes);
+    }
+
+    // at depth 0 there are no moves to split by, just the position itself
+    let total: u64 = match depth {
+        0 => perft(board, 0),
+        _ => counts.iter().map(|(_, nodes)| nodes).sum(),
+    };
+    println!();
+    println!("Nodes searched: {}", total);
+}
@@ -1,72 +1,610 @@
-use crate::PositionCounter;
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
 use crate::board::Board;
-use crate::board::PieceType;
+use crate::board::{PieceColor, PieceType};
+use crate::moves::{Move, MoveList};
+use crate::time_manager::TimeManager;
+
+// the outcome of a search: what to play, why, and how much work it took
+pub struct SearchResult {
+    best_move: Option<Move>,
+    pv: Vec<Move>,
+    score: isize,
+    depth: usize,
+    stats: SearchStats,
+}
+
+impl SearchResult {
+    pub fn get_best_move(&self) -> Option<Move> {
+        self.best_move
+    }
+
+    // principal variation, starting with the best move
+    pub fn get_pv(&self) -> &[Move] {
+        &self.pv
+    }
+
+    // score in centipawns from the side to move's point of view
+    pub fn get_score(&self) -> isize {
+        self.score
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn get_nodes(&self) -> usize {
+        self.stats.get_positions()
+    }
+}
+
+// counters collected while searching
+#[derive(Clone, Default)]
+pub struct SearchStats {
+    positions: usize,
+}
+
+impl SearchStats {
+    pub fn new() -> Self {
+        SearchStats { positions: 0 }
+    }
+
+    pub fn add_position(&mut self) {
+        self.positions += 1;
+    }
+
+    pub fn get_positions(&self) -> usize {
+        self.positions
+    }
+}
+
+// bound on every score, unlike isize::MIN it can be negated safely
+const INFINITY: isize = 1_000_000;
+// score for mating at the root, a mate `ply` plies away scores MATE - ply so
+// quicker mates score higher and slower ways of being mated less badly
+pub const MATE: isize = 100_000;
+// deepest a search can go, scores within this of MATE are mates
+const MAX_PLY: isize = 256;
+
+// whether quiescence answers a check with every evasion instead of standing pat
+const CHECK_EVASIONS: bool = true;
+// slack in centipawns on a capture's gain before delta pruning gives up on it
+const DELTA_MARGIN: isize = 200;
+
+// how often, in nodes, the search looks at the clock
+const CHECK_INTERVAL: usize = 1024;
+
+// what a search may spend besides being stopped, unlimited unless set
+#[derive(Copy, Clone, Default)]
+pub struct SearchLimits {
+    depth: Option<usize>,
+    nodes: Option<usize>,
+    deadline: Option<Instant>,
+    time: Option<TimeManager>,
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        SearchLimits::default()
+    }
+
+    pub fn get_depth(&self) -> Option<usize> {
+        self.depth
+    }
+
+    pub fn get_nodes(&self) -> Option<usize> {
+        self.nodes
+    }
+
+    pub fn with_depth(self, depth: usize) -> Self {
+        SearchLimits { depth: Some(depth), ..self }
+    }
+
+    pub fn with_nodes(self, nodes: usize) -> Self {
+        SearchLimits { nodes: Some(nodes), ..self }
+    }
+
+    // leaves it to the time manager when to stop, cutting the search off at
+    // its hard limit
+    pub fn with_time(self, time: TimeManager) -> Self {
+        SearchLimits { deadline: Some(time.get_deadline()), time: Some(time), ..self }
+    }
+}
 
-pub fn search(board: &Board, depth: usize,  maximizing_player: bool,
-        position_counter: &mut PositionCounter) -> isize {
+// what a running search needs to know whether to give up
+struct SearchState<'a> {
+    stop: &'a AtomicBool,
+    limits: SearchLimits,
+    stats: SearchStats,
+    // raised once the search ran out of budget, the iteration that saw it is lost
+    aborted: bool,
+    // the first iteration always finishes so there is a move to play
+    can_abort: bool,
+}
+
+impl SearchState<'_> {
+    // counts a node and checks whether the search has to give up
+    fn visit(&mut self) -> bool {
+        self.stats.add_position();
+        if !self.can_abort {
+            return false;
+        }
+
+        // reading the clock is slow enough to only do it now and then
+        let nodes = self.stats.get_positions();
+        if self.stop.load(Ordering::Relaxed)
+            || self.limits.nodes.is_some_and(|limit| nodes >= limit)
+            || (nodes.is_multiple_of(CHECK_INTERVAL)
+                && self.limits.deadline.is_some_and(|d| Instant::now() >= d)) {
+            self.aborted = true;
+        }
+
+        self.aborted
+    }
+}
+
+// searches one ply deeper at a time until the limits run out or `stop` is
+// raised, returning the line from the last depth searched to the end. `report`
+// is handed the result of every finished depth
+pub fn search(board: &mut Board, limits: SearchLimits, stop: &AtomicBool,
+        mut report: impl FnMut(&SearchResult)) -> SearchResult {
+    let max_depth = limits.depth.unwrap_or(MAX_PLY as usize).clamp(1, MAX_PLY as usize);
+    let mut state = SearchState {
+        stop,
+        limits,
+        stats: SearchStats::new(),
+        aborted: false,
+        can_abort: false,
+    };
+    let mut result = SearchResult {
+        best_move: None,
+        pv: Vec::new(),
+        score: 0,
+        depth: 0,
+        stats: SearchStats::new(),
+    };
+
+    let root_moves = board.get_board_legal_moves().len();
+
+    for depth in 1..=max_depth {
+        let (pv, score) = match search_root(board, depth, &mut state) {
+            Some(line) => line,
+            None => break,
+        };
+        result = SearchResult {
+            best_move: pv.first().copied(),
+            pv,
+            score,
+            depth,
+            stats: state.stats.clone(),
+        };
+        report(&result);
+        state.can_abort = true;
+
+        if let Some(time) = &mut state.limits.time {
+            time.update(result.best_move, score, root_moves);
+            if time.should_stop() {
+                break;
+            }
+        }
+
+        // nothing left to find once there are no moves or the full-width search
+        // has seen the mate. quiescence can find longer mates past the depth,
+        // which a deeper iteration may still beat with a shorter quiet one
+        if result.best_move.is_none() || MATE - score.abs() <= depth as isize {
+            break;
+        }
+    }
+
+    result.stats = state.stats;
+    result
+}
+
+// searches every root move to `depth`, giving the principal variation and its
+// score, or None if the search was aborted before it finished
+fn search_root(board: &mut Board, depth: usize, state: &mut SearchState)
+        -> Option<(Vec<Move>, isize)> {
+    let moves = board.get_board_legal_moves();
+    if moves.is_empty() {
+        return Some((Vec::new(), terminal_score(board, 0)));
+    }
+
+    let mut pv = Vec::new();
+    let mut best_score = -INFINITY;
+    for m in moves {
+        let undo = board.make_move(m);
+        let mut child_pv = Vec::new();
+        // only moves beating the best so far matter, so they search a narrower window
+        let score = -alpha_beta(board, depth - 1, 1, -INFINITY, -best_score, &mut child_pv,
+                                state);
+        board.unmake_move(m, undo);
+        if state.aborted {
+            return None;
+        }
+
+        if pv.is_empty() || score > best_score {
+            best_score = score;
+            pv = update_pv(m, child_pv);
+        }
+    }
+
+    Some((pv, best_score))
+}
+
+// negamax with alpha-beta pruning. scores are from the side to move's point of
+// view, and once a move reaches beta the rest can't matter: the opponent won't
+// allow this position. fail-soft, a score outside (alpha, beta) is a bound on
+// the true score rather than clamped to the window. `ply` counts the moves
+// played since the root. once the search is aborted the scores it returns are
+// meaningless, and callers have to check for it
+fn alpha_beta(board: &mut Board, depth: usize, ply: usize, mut alpha: isize, beta: isize,
+        pv: &mut Vec<Move>, state: &mut SearchState) -> isize {
     if depth == 0 {
-        position_counter.add();
-        println!("{}", position_counter.positions);
-        return evaluate(board);
+        return quiescence(board, ply, alpha, beta, state);
+    }
+    if state.visit() {
+        return 0;
     }
 
-    if maximizing_player {
-        let mut max = isize::MIN;
-        for (original_position, potential_positions) in board.get_board_legal_moves(true) {
-            for potential_position in potential_positions {
-                let simulated_move_board = board.simulate_move(
-                    original_position, potential_position);
-                let score = search(&simulated_move_board, depth - 1, 
-                                   !maximizing_player, position_counter);
-                if score > max {
-                    max = score;
-                }
+    let moves = board.get_board_legal_moves();
+    if moves.is_empty() {
+        return terminal_score(board, ply);
+    }
+
+    let mut best = -INFINITY;
+    for m in moves {
+        let undo = board.make_move(m);
+        let mut child_pv = Vec::new();
+        let score = -alpha_beta(board, depth - 1, ply + 1, -beta, -alpha, &mut child_pv,
+                                state);
+        board.unmake_move(m, undo);
+        if state.aborted {
+            return 0;
+        }
+
+        if score > best {
+            best = score;
+            if score > alpha {
+                alpha = score;
+                *pv = update_pv(m, child_pv);
+            }
+            if alpha >= beta {
+                break;
             }
         }
-        return max;
+    }
+
+    best
+}
+
+// searches captures and promotions until the position is quiet, so a leaf
+// isn't scored in the middle of an exchange. the side to move may stand pat on
+// the static evaluation rather than capture, except when in check where every
+// evasion is searched and having none is mate
+fn quiescence(board: &mut Board, ply: usize, mut alpha: isize, beta: isize,
+        state: &mut SearchState) -> isize {
+    // a long enough run of checks and captures would otherwise go on past the
+    // ply mate scores are counted in
+    if ply >= MAX_PLY as usize {
+        return evaluate(board);
+    }
+    if state.visit() {
+        return 0;
+    }
+
+    let in_check = CHECK_EVASIONS && board.in_check();
+    let mut stand_pat = -INFINITY;
+    let mut best = -INFINITY;
+    let mut moves = MoveList::new();
+
+    if in_check {
+        board.generate_moves(&mut moves);
     } else {
-        let mut min = isize::MAX;
-        for (original_position, potential_positions) in board.get_board_legal_moves(false) {
-            for potential_position in potential_positions {
-                let simulated_move_board = board.simulate_move(
-                    original_position, potential_position);
-                let score = search(&simulated_move_board, depth - 1, 
-                                   !maximizing_player, position_counter);
-                if score < min {
-                    min = score;
-                }
+        // the evaluation is only worth standing on when not in check
+        stand_pat = evaluate(board);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+        best = stand_pat;
+        board.generate_captures(&mut moves);
+    }
+    board.retain_legal(&mut moves);
+
+    if in_check && moves.is_empty() {
+        return -(MATE - ply as isize);
+    }
+
+    // most valuable victim first, taken by the least valuable attacker, so
+    // the captures most likely to cut off come early
+    moves.sort_by_key(|&m| (Reverse(material_gain(m)), m.get_piece() as u8));
+
+    for m in moves {
+        // delta pruning: skip captures that couldn't bring the score up to
+        // alpha even winning the piece with a margin to spare
+        if !in_check && stand_pat + material_gain(m) + DELTA_MARGIN <= alpha {
+            continue;
+        }
+
+        let undo = board.make_move(m);
+        let score = -quiescence(board, ply + 1, -beta, -alpha, state);
+        board.unmake_move(m, undo);
+        if state.aborted {
+            return 0;
+        }
+
+        if score > best {
+            best = score;
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
             }
         }
+    }
+
+    best
+}
 
-        return min;
+// material the move wins outright: the piece it takes, and what a promotion
+// adds over the pawn
+fn material_gain(m: Move) -> isize {
+    let mut gain = piece_value(m.get_captured());
+    if m.is_promotion() {
+        gain += piece_value(m.get_promotion()) - piece_value(PieceType::Pawn);
     }
+
+    gain
 }
 
+// score of a position without legal moves: mated `ply` plies from the root if
+// in check, otherwise stalemate which is a draw
+fn terminal_score(board: &Board, ply: usize) -> isize {
+    if board.in_check() {
+        -(MATE - ply as isize)
+    } else {
+        0
+    }
+}
+
+// moves until mate for a mate score, negative when the side to move is the one
+// getting mated, as UCI reports it with `score mate`. None for other scores
+pub fn mate_in(score: isize) -> Option<isize> {
+    if score.abs() < MATE - MAX_PLY {
+        return None;
+    }
+
+    let moves = (MATE - score.abs() + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+// builds a principal variation from a move and the line that follows it
+fn update_pv(first: Move, mut rest: Vec<Move>) -> Vec<Move> {
+    rest.insert(0, first);
+    rest
+}
+
+// material balance from the side to move's point of view
 fn evaluate(board: &Board) -> isize {
-    let mut white_weight = 0;
-    let mut black_weight = 0;
-
-    for white_piece in board.get_white_pieces() {
-        white_weight += match white_piece.get_type() {
-            PieceType::Pawn => 1,
-            PieceType::Rook => 5,
-            PieceType::Bishop => 3,
-            PieceType::Knight => 3,
-            PieceType::Queen => 9,
-            _ => 0,
-        };
+    let mut score = 0;
+
+    for piece_type in [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook,
+                       PieceType::Queen] {
+        let white = board.get_pieces_bitboard(piece_type, PieceColor::White).count_ones();
+        let black = board.get_pieces_bitboard(piece_type, PieceColor::Black).count_ones();
+        score += piece_value(piece_type) * (white as isize - black as isize);
+    }
+
+    match board.get_side_to_move() {
+        PieceColor::Black => -score,
+        _ => score,
+    }
+}
+
+// value of a piece in centipawns, kings are never traded so count for nothing
+fn piece_value(piece_type: PieceType) -> isize {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 300,
+        PieceType::Bishop => 300,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King | PieceType::Empty => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_to(board: &mut Board, depth: usize) -> SearchResult {
+        search(board, SearchLimits::new().with_depth(depth), &AtomicBool::new(false), |_| {})
     }
 
-    for black_piece in board.get_black_pieces() {
-        black_weight -= match black_piece.get_type() {
-            PieceType::Pawn => 1,
-            PieceType::Rook => 5,
-            PieceType::Bishop => 3,
-            PieceType::Knight => 3,
-            PieceType::Queen => 9,
-            _ => 0,
+    // the plain minimax search replaced by alpha_beta, kept to check it against.
+    // white maximizes the score and black minimizes it, leaves get the same
+    // quiescence search with an open window
+    fn minimax(board: &mut Board, depth: usize, ply: usize, state: &mut SearchState) -> isize {
+        if depth == 0 {
+            let score = quiescence(board, ply, -INFINITY, INFINITY, state);
+            return match board.get_side_to_move() {
+                PieceColor::Black => -score,
+                _ => score,
+            };
+        }
+        state.visit();
+
+        let white_to_move = board.get_side_to_move() == PieceColor::White;
+        let mut best = if white_to_move { isize::MIN } else { isize::MAX };
+        for m in board.get_board_legal_moves() {
+            let undo = board.make_move(m);
+            let score = minimax(board, depth - 1, ply + 1, state);
+            board.unmake_move(m, undo);
+            best = if white_to_move { best.max(score) } else { best.min(score) };
+        }
+
+        best
+    }
+
+    // the first root move with the best minimax score, that score from the side
+    // to move's point of view, and the nodes visited
+    fn minimax_root(board: &mut Board, depth: usize) -> (Move, isize, usize) {
+        let sign = match board.get_side_to_move() {
+            PieceColor::Black => -1,
+            _ => 1,
+        };
+        let stop = AtomicBool::new(false);
+        let mut state = SearchState {
+            stop: &stop,
+            limits: SearchLimits::new(),
+            stats: SearchStats::new(),
+            aborted: false,
+            can_abort: false,
         };
+        let mut best: Option<(Move, isize)> = None;
+
+        for m in board.get_board_legal_moves() {
+            let undo = board.make_move(m);
+            let score = sign * minimax(board, depth - 1, 1, &mut state);
+            board.unmake_move(m, undo);
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((m, score));
+            }
+        }
+
+        let (best_move, score) = best.unwrap();
+        (best_move, score, state.stats.get_positions())
+    }
+
+    // checks alpha_beta picks the same move and score as minimax while
+    // visiting far fewer positions
+    fn assert_matches_minimax(fen: &str, depth: usize) {
+        let mut board = Board::from_fen(fen).unwrap();
+        let (best_move, score, minimax_nodes) = minimax_root(&mut board, depth);
+        let result = search_to(&mut board, depth);
+
+        assert_eq!(result.get_best_move(), Some(best_move), "{}", fen);
+        assert_eq!(result.get_score(), score, "{}", fen);
+        assert!(result.get_nodes() * 2 < minimax_nodes, "{}: {} nodes against {}",
+                fen, result.get_nodes(), minimax_nodes);
+        assert_eq!(board.get_fen(), fen);
+    }
+
+    #[test]
+    fn alpha_beta_matches_minimax() {
+        // none of these have a mate within the depth, which minimax can't score
+        assert_matches_minimax(crate::board::START_FEN, 3);
+        assert_matches_minimax(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 0 5", 3);
+        assert_matches_minimax(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq d3 0 3", 3);
+        assert_matches_minimax("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4);
+    }
+
+    // castling and en passant under the search. minimax needs minutes for it even
+    // in a release build, since every one of its leaves runs a full-window
+    // quiescence search through Kiwipete's many captures
+    #[test]
+    #[ignore]
+    fn alpha_beta_matches_minimax_on_kiwipete() {
+        assert_matches_minimax(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3);
+    }
+
+    #[test]
+    fn finds_the_quickest_mate() {
+        // Ra8 mates at once, slower mates are also on the board
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let result = search_to(&mut board, 3);
+
+        assert_eq!(result.get_best_move().map(|m| m.to_string()), Some("a1a8".to_string()));
+        assert_eq!(result.get_score(), MATE - 1);
+        assert_eq!(mate_in(result.get_score()), Some(1));
+    }
+
+    #[test]
+    fn keeps_searching_past_a_mate_found_by_quiescence() {
+        // at depth 1 the checks and captures of quiescence already mate in 4,
+        // it takes depth 3 to find the mate in 2
+        let fen = "rnb2k1r/p2Pbpp1/2pq3p/1pQ5/1P3N2/2N5/P1P1BnPP/R1B1K2R w KQ - 2 15";
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(mate_in(search_to(&mut board, 1).get_score()), Some(4));
+
+        let result = search_to(&mut board, 6);
+        assert_eq!(result.get_score(), MATE - 3);
+        assert_eq!(result.get_depth(), 3);
+    }
+
+    #[test]
+    fn scores_mated_and_stalemated_positions() {
+        let mut mated = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        let result = search_to(&mut mated, 2);
+        assert_eq!(result.get_best_move(), None);
+        assert_eq!(result.get_score(), -MATE);
+
+        let mut stalemated = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let result = search_to(&mut stalemated, 2);
+        assert_eq!(result.get_best_move(), None);
+        assert_eq!(result.get_score(), 0);
+
+        // being mated in two moves, after white's reply and then one more
+        assert_eq!(mate_in(-(MATE - 4)), Some(-2));
+        assert_eq!(mate_in(MATE - 3), Some(2));
+        assert_eq!(mate_in(900), None);
+    }
+
+    #[test]
+    fn keeps_the_last_finished_depth_when_out_of_nodes() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let mut depths = Vec::new();
+        let result = search(&mut board, SearchLimits::new().with_nodes(20_000),
+                            &AtomicBool::new(false), |result| depths.push(result.get_depth()));
+
+        assert!(result.get_nodes() <= 20_000);
+        assert_eq!(depths, (1..=result.get_depth()).collect::<Vec<_>>());
+        let complete = search_to(&mut board, result.get_depth());
+        assert_eq!(result.get_best_move(), complete.get_best_move());
+        assert_eq!(result.get_score(), complete.get_score());
+        assert_eq!(board.get_fen(), fen);
+    }
+
+    #[test]
+    fn stopped_search_still_finishes_the_first_depth() {
+        let mut board = Board::new();
+        let stop = AtomicBool::new(true);
+        let result = search(&mut board, SearchLimits::new(), &stop, |_| {});
+
+        assert_eq!(result.get_depth(), 1);
+        assert!(result.get_best_move().is_some());
+    }
+
+    #[test]
+    fn quiescence_sees_the_recapture() {
+        // Qxd5 wins a pawn at depth 1 unless the search looks past cxd5
+        let mut board = Board::from_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let result = search_to(&mut board, 1);
+
+        assert_ne!(result.get_best_move().map(|m| m.to_string()), Some("d1d5".to_string()));
+        assert_eq!(result.get_score(), 700);
     }
 
-    white_weight + black_weight
+    #[test]
+    fn quiescence_stops_at_max_ply() {
+        // white is in check and can take the queen, neither gets searched
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4q3/4K3 w - - 0 1").unwrap();
+        let stop = AtomicBool::new(false);
+        let mut state = SearchState {
+            stop: &stop,
+            limits: SearchLimits::new(),
+            stats: SearchStats::new(),
+            aborted: false,
+            can_abort: false,
+        };
+
+        let score = quiescence(&mut board, MAX_PLY as usize, -INFINITY, INFINITY, &mut state);
+        assert_eq!(score, evaluate(&board));
+        assert_eq!(state.stats.get_positions(), 0);
+    }
 }
@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+
+use crate::moves::Move;
+
+// assumed number of moves left when the GUI doesn't send movestogo
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+// milliseconds kept back for sending the move and GUI lag
+const MOVE_OVERHEAD: u64 = 20;
+// never plan to spend more of the clock than this share (in percent) on one move
+const MAX_CLOCK_SHARE: u64 = 50;
+// how far past the soft limit the hard limit lets the search run, in percent
+const HARD_LIMIT_SCALE: u64 = 400;
+
+// soft limit extensions, in percent, when the root is unsettled
+const BEST_MOVE_CHANGE_EXTENSION: u32 = 70;
+const SCORE_DROP_EXTENSION: u32 = 50;
+// centipawns the score has to fall by between iterations to count as a drop
+const SCORE_DROP: isize = 30;
+// iterations the best move has to hold for it to be obvious, and how much of
+// the soft limit (in percent) an obvious move gets
+const STABLE_ITERATIONS: usize = 6;
+const OBVIOUS_MOVE_SCALE: u32 = 50;
+
+// decides how long to think about a move. iterations stop starting once the
+// soft limit has passed, which grows when the best move or score is unsettled
+// and shrinks for obvious moves. the hard limit is where the search is cut off
+#[derive(Copy, Clone)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    // a fixed movetime gets used in full unless the move is forced
+    fixed: bool,
+    // soft limit scale in percent
+    scale: u32,
+    best_move: Option<Move>,
+    score: Option<isize>,
+    stable_iterations: usize,
+    forced: bool,
+}
+
+impl TimeManager {
+    // a fixed time per move, as with `go movetime`, in milliseconds
+    pub fn fixed(move_time: u64) -> Self {
+        let limit = Duration::from_millis(move_time.saturating_sub(MOVE_OVERHEAD));
+        TimeManager::new(limit, limit, true)
+    }
+
+    // the time left on the clock and the increment per move, in milliseconds,
+    // and the moves left until the next time control if there is one
+    pub fn clock(time: u64, increment: u64, moves_to_go: Option<u64>) -> Self {
+        let available = time.saturating_sub(MOVE_OVERHEAD);
+        let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let max_share = available * MAX_CLOCK_SHARE / 100;
+
+        let soft_limit = (available / moves_to_go + increment).min(max_share);
+        let hard_limit = (soft_limit * HARD_LIMIT_SCALE / 100).min(max_share);
+
+        TimeManager::new(Duration::from_millis(soft_limit), Duration::from_millis(hard_limit),
+                         false)
+    }
+
+    fn new(soft_limit: Duration, hard_limit: Duration, fixed: bool) -> Self {
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            fixed,
+            scale: 100,
+            best_move: None,
+            score: None,
+            stable_iterations: 0,
+            forced: false,
+        }
+    }
+
+    pub fn get_soft_limit(&self) -> Duration {
+        self.soft_limit
+    }
+
+    pub fn get_hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    // when the search has to stop, even in the middle of an iteration
+    pub fn get_deadline(&self) -> Instant {
+        self.start + self.hard_limit
+    }
+
+    // takes in the result of a finished iteration, along with how many legal
+    // moves the root has
+    pub fn update(&mut self, best_move: Option<Move>, score: isize, root_moves: usize) {
+        let changed = self.best_move.is_some() && best_move != self.best_move;
+        let dropped = self.score.is_some_and(|last| last - score >= SCORE_DROP);
+
+        self.stable_iterations = if changed { 0 } else { self.stable_iterations + 1 };
+        self.scale = 100;
+        if changed {
+            self.scale += BEST_MOVE_CHANGE_EXTENSION;
+        }
+        if dropped {
+            self.scale += SCORE_DROP_EXTENSION;
+        }
+        if !self.fixed && !changed && !dropped && self.stable_iterations >= STABLE_ITERATIONS {
+            self.scale = OBVIOUS_MOVE_SCALE;
+        }
+
+        self.forced = root_moves <= 1;
+        self.best_move = best_move;
+        self.score = Some(score);
+    }
+
+    // whether to stop rather than start another iteration
+    pub fn should_stop(&self) -> bool {
+        self.should_stop_after(self.start.elapsed())
+    }
+
+    fn should_stop_after(&self, elapsed: Duration) -> bool {
+        if self.forced {
+            return true;
+        }
+
+        let soft_limit = if self.fixed {
+            self.hard_limit
+        } else {
+            (self.soft_limit * self.scale / 100).min(self.hard_limit)
+        };
+        elapsed >= soft_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::PieceType;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn limits_from_the_clock() {
+        let time = TimeManager::clock(60_020, 1_000, None);
+        assert_eq!(time.get_soft_limit(), ms(3_000));
+        assert_eq!(time.get_hard_limit(), ms(12_000));
+
+        // the last move before the time control can't use the whole clock
+        let time = TimeManager::clock(10_020, 0, Some(1));
+        assert_eq!(time.get_soft_limit(), ms(5_000));
+        assert_eq!(time.get_hard_limit(), ms(5_000));
+
+        let time = TimeManager::fixed(1_020);
+        assert_eq!(time.get_soft_limit(), ms(1_000));
+        assert_eq!(time.get_hard_limit(), ms(1_000));
+    }
+
+    #[test]
+    fn unsettled_root_gets_more_time() {
+        let e2e4 = Move::new(0x14, 0x34, PieceType::Pawn, PieceType::Empty);
+        let d2d4 = Move::new(0x13, 0x33, PieceType::Pawn, PieceType::Empty);
+        let mut time = TimeManager::clock(60_020, 0, Some(20));
+
+        time.update(Some(e2e4), 20, 20);
+        assert!(!time.should_stop_after(ms(2_900)));
+        assert!(time.should_stop_after(ms(3_000)));
+
+        // a new best move with a lower score
+        time.update(Some(d2d4), -40, 20);
+        assert!(!time.should_stop_after(ms(6_500)));
+        assert!(time.should_stop_after(ms(6_600)));
+    }
+
+    #[test]
+    fn forced_and_obvious_moves_stop_early() {
+        let e2e4 = Move::new(0x14, 0x34, PieceType::Pawn, PieceType::Empty);
+        let mut time = TimeManager::clock(60_020, 0, Some(20));
+
+        for _ in 0..STABLE_ITERATIONS {
+            time.update(Some(e2e4), 20, 20);
+        }
+        assert!(time.should_stop_after(ms(1_500)));
+
+        time.update(Some(e2e4), 20, 1);
+        assert!(time.should_stop_after(ms(0)));
+    }
+}
@@ -0,0 +1,381 @@
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::attacks;
+use crate::board::{Board, PieceColor};
+use crate::perft;
+use crate::search::{self, SearchLimits, SearchResult};
+use crate::time_manager::TimeManager;
+
+const ENGINE_NAME: &str = "DanteEngine";
+const ENGINE_AUTHOR: &str = "dgrco";
+
+// depth used when `go` doesn't ask for one
+const DEFAULT_DEPTH: usize = 4;
+
+// parameters of a `go` command, times are in milliseconds
+#[derive(Default)]
+struct GoParams {
+    depth: Option<usize>,
+    nodes: Option<usize>,
+    movetime: Option<u64>,
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: Option<u64>,
+    binc: Option<u64>,
+    movestogo: Option<u64>,
+    infinite: bool,
+    // set for a perft run instead of a search, with its depth if one could be read
+    perft: Option<Option<usize>>,
+}
+
+struct Uci {
+    board: Board,
+    stop: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
+}
+
+// reads UCI commands from stdin until `quit` or end of input
+pub fn run() {
+    attacks::init();
+    let mut uci = Uci::new();
+
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !uci.handle_command(&line) {
+            break;
+        }
+    }
+
+    uci.stop_search();
+}
+
+impl Uci {
+    fn new() -> Self {
+        Uci {
+            board: Board::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+        }
+    }
+
+    // handles a single command, returns false once the engine should exit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+
+        match command {
+            "uci" => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author {}", ENGINE_AUTHOR);
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop_search();
+                self.board = Board::new();
+            }
+            "position" => {
+                self.stop_search();
+                self.set_position(args);
+            }
+            "go" => {
+                self.stop_search();
+                self.go(parse_go(args));
+            }
+            "stop" => self.stop_search(),
+            "quit" => return false,
+            // non-standard, prints the current position for debugging
+            "d" => {
+                self.board.print_board();
+                println!("Fen: {}", self.board.get_fen());
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    // position [startpos | fen <fen>] [moves <move>...]
+    fn set_position(&mut self, args: &[&str]) {
+        let (setup, moves) = match args.iter().position(|&arg| arg == "moves") {
+            Some(i) => (&args[..i], &args[i + 1..]),
+            None => (args, &[][..]),
+        };
+
+        match setup.first() {
+            Some(&"startpos") => {
+                self.board = Board::new();
+            }
+            Some(&"fen") => match Board::from_fen(&setup[1..].join(" ")) {
+                Ok(board) => self.board = board,
+                Err(error) => {
+                    println!("info string invalid fen: {}", error);
+                    return;
+                }
+            },
+            _ => return,
+        }
+
+        for long_algebraic in moves {
+            match self.board.move_from_uci(long_algebraic) {
+                Some(m) => self.board = self.board.simulate_move(m),
+                None => {
+                    println!("info string invalid move: {}", long_algebraic);
+                    break;
+                }
+            }
+        }
+    }
+
+    // starts searching the current position on a background thread, or runs
+    // perft on it when asked to
+    fn go(&mut self, params: GoParams) {
+        match params.perft {
+            Some(Some(depth)) => {
+                perft::print_divide(&mut self.board.clone(), depth);
+                return;
+            }
+            Some(None) => {
+                println!("info string perft needs a depth");
+                return;
+            }
+            None => {}
+        }
+
+        let mut board = self.board.clone();
+        let limits = search_limits(&params, board.get_side_to_move());
+        let infinite = params.infinite;
+
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = Arc::clone(&self.stop);
+
+        self.search_thread = Some(thread::spawn(move || {
+            let result = search::search(&mut board, limits, &stop, print_info);
+
+            // bestmove may only be sent after `stop` when searching infinitely
+            while infinite && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            match result.get_best_move() {
+                Some(best_move) => println!("bestmove {}", best_move),
+                None => println!("bestmove 0000"),
+            }
+        }));
+    }
+
+    // stops any running search and waits for it to report its move
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.search_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// reports each depth the search finishes
+fn print_info(result: &SearchResult) {
+    println!("{}", info_line(result));
+}
+
+// the info line for a finished depth, leaving out the pv when there's no
+// move to play
+fn info_line(result: &SearchResult) -> String {
+    let pv: Vec<String> = result.get_pv().iter().map(|m| m.to_string()).collect();
+
+    let score = match search::mate_in(result.get_score()) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.get_score()),
+    };
+
+    let mut line = format!("info depth {} score {} nodes {}",
+                           result.get_depth(), score, result.get_nodes());
+    if !pv.is_empty() {
+        line += &format!(" pv {}", pv.join(" "));
+    }
+    line
+}
+
+fn parse_go(args: &[&str]) -> GoParams {
+    let mut params = GoParams::default();
+    let mut iter = args.iter();
+
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "infinite" => params.infinite = true,
+            "perft" => params.perft = Some(iter.next().and_then(|v| v.parse().ok())),
+            "depth" => params.depth = iter.next().and_then(|v| v.parse().ok()),
+            "nodes" => params.nodes = iter.next().and_then(|v| v.parse().ok()),
+            "movetime" => params.movetime = iter.next().and_then(|v| v.parse().ok()),
+            "wtime" => params.wtime = iter.next().and_then(|v| v.parse().ok()),
+            "btime" => params.btime = iter.next().and_then(|v| v.parse().ok()),
+            "winc" => params.winc = iter.next().and_then(|v| v.parse().ok()),
+            "binc" => params.binc = iter.next().and_then(|v| v.parse().ok()),
+            "movestogo" => params.movestogo = iter.next().and_then(|v| v.parse().ok()),
+            _ => {}
+        }
+    }
+
+    params
+}
+
+// the depth, nodes and time the search may spend, searching to DEFAULT_DEPTH
+// when `go` gives none of them
+fn search_limits(params: &GoParams, side_to_move: PieceColor) -> SearchLimits {
+    let mut limits = SearchLimits::new();
+    let time = time_manager(params, side_to_move);
+
+    if let Some(depth) = params.depth {
+        limits = limits.with_depth(depth);
+    }
+    if let Some(nodes) = params.nodes {
+        limits = limits.with_nodes(nodes);
+    }
+    if let Some(time) = time {
+        limits = limits.with_time(time);
+    }
+    if params.depth.is_none() && params.nodes.is_none() && time.is_none() && !params.infinite {
+        limits = limits.with_depth(DEFAULT_DEPTH);
+    }
+
+    limits
+}
+
+// sets up the time manager for a timed search, None when searching without a clock
+fn time_manager(params: &GoParams, side_to_move: PieceColor) -> Option<TimeManager> {
+    if params.infinite {
+        return None;
+    }
+    if let Some(movetime) = params.movetime {
+        return Some(TimeManager::fixed(movetime));
+    }
+
+    let (time, increment) = match side_to_move {
+        PieceColor::Black => (params.btime?, params.binc.unwrap_or(0)),
+        _ => (params.wtime?, params.winc.unwrap_or(0)),
+    };
+
+    Some(TimeManager::clock(time, increment, params.movestogo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+
+    fn go(args: &str) -> GoParams {
+        parse_go(&args.split_whitespace().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_go_parameters() {
+        let params = go("wtime 60000 btime 50000 winc 1000 binc 500 movestogo 20 depth 6 nodes 900");
+        assert_eq!(params.wtime, Some(60000));
+        assert_eq!(params.btime, Some(50000));
+        assert_eq!(params.winc, Some(1000));
+        assert_eq!(params.binc, Some(500));
+        assert_eq!(params.movestogo, Some(20));
+        assert_eq!(params.depth, Some(6));
+        assert_eq!(params.nodes, Some(900));
+        assert!(!params.infinite);
+
+        let params = go("infinite movetime 300 perft 3");
+        assert!(params.infinite);
+        assert_eq!(params.movetime, Some(300));
+        assert_eq!(params.perft, Some(Some(3)));
+
+        // unknown words are skipped and unreadable values left unset
+        let params = go("ponder depth x searchmoves e2e4 movetime");
+        assert_eq!(params.depth, None);
+        assert_eq!(params.movetime, None);
+
+        // perft is still asked for without a depth it can read
+        assert_eq!(go("perft x").perft, Some(None));
+        assert_eq!(go("perft").perft, Some(None));
+    }
+
+    #[test]
+    fn sets_up_positions() {
+        let mut uci = Uci::new();
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        uci.set_position(&format!("fen {} moves e2e4 e8d7", fen).split(' ').collect::<Vec<_>>());
+        assert_eq!(uci.board.get_fen(), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+
+        // a bad fen or a missing setup leaves the position alone
+        uci.set_position(&["fen", "4k3/8", "w", "-", "-"]);
+        assert_eq!(uci.board.get_fen(), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+        uci.set_position(&[]);
+        assert_eq!(uci.board.get_fen(), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+
+        uci.set_position(&["startpos"]);
+        assert_eq!(uci.board.get_fen(), START_FEN);
+    }
+
+    #[test]
+    fn info_leaves_out_an_empty_pv() {
+        let stop = AtomicBool::new(false);
+        let limits = SearchLimits::new().with_depth(3);
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        let result = search::search(&mut board, limits, &stop, |_| {});
+        assert!(info_line(&result).ends_with(" pv a1a8"));
+
+        // stalemated, so there's nothing to play
+        let mut board = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let result = search::search(&mut board, limits, &stop, |_| {});
+        assert_eq!(info_line(&result),
+                   format!("info depth 1 score cp 0 nodes {}", result.get_nodes()));
+    }
+
+    #[test]
+    fn position_rejects_moves_for_the_wrong_side() {
+        let mut uci = Uci::new();
+        uci.set_position(&["startpos", "moves", "e7e5"]);
+        assert_eq!(uci.board.get_fen(), START_FEN);
+
+        // the moves before the bad one are kept
+        uci.set_position(&["startpos", "moves", "e2e4", "d2d4"]);
+        assert_eq!(uci.board.get_fen(),
+                   "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+    }
+
+    #[test]
+    fn picks_search_limits() {
+        let limits = search_limits(&go(""), PieceColor::White);
+        assert_eq!(limits.get_depth(), Some(DEFAULT_DEPTH));
+        assert_eq!(search_limits(&go("depth 7"), PieceColor::White).get_depth(), Some(7));
+
+        // any other limit replaces the default depth
+        let limits = search_limits(&go("nodes 5000"), PieceColor::White);
+        assert_eq!(limits.get_depth(), None);
+        assert_eq!(limits.get_nodes(), Some(5000));
+        assert_eq!(search_limits(&go("movetime 100"), PieceColor::White).get_depth(), None);
+        assert_eq!(search_limits(&go("infinite"), PieceColor::White).get_depth(), None);
+        assert_eq!(search_limits(&go("wtime 1000"), PieceColor::White).get_depth(), None);
+    }
+
+    #[test]
+    fn picks_the_clock_of_the_side_to_move() {
+        let params = go("wtime 60020 btime 30020 winc 1000 binc 0 movestogo 10");
+        let white = time_manager(&params, PieceColor::White).unwrap();
+        let black = time_manager(&params, PieceColor::Black).unwrap();
+        assert_eq!(white.get_soft_limit(), Duration::from_millis(7_000));
+        assert_eq!(black.get_soft_limit(), Duration::from_millis(3_000));
+
+        let fixed = time_manager(&go("movetime 520 wtime 60000"), PieceColor::White).unwrap();
+        assert_eq!(fixed.get_hard_limit(), Duration::from_millis(500));
+
+        assert!(time_manager(&go("infinite wtime 60000"), PieceColor::White).is_none());
+        // only the other side's clock was given
+        assert!(time_manager(&go("btime 60000"), PieceColor::White).is_none());
+    }
+}
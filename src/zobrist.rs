@@ -0,0 +1,88 @@
+use std::sync::OnceLock;
+
+use crate::board::{PieceColor, PieceType};
+
+// seed for the keys, fixed so hashes are the same from run to run
+const ZOBRIST_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// a random key for every piece on every square and every other part of the
+// position. a position's hash is the xor of the keys for what it holds, so
+// adding or removing any one of them is a single xor
+struct Keys {
+    // indexed by color, piece type (pawn to king) and square
+    pieces: [[[u64; 64]; 6]; 2],
+    black_to_move: u64,
+    // indexed by the castling rights bits
+    castling: [u64; 16],
+    // indexed by the file of the en passant square
+    en_passant: [u64; 8],
+}
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+
+fn keys() -> &'static Keys {
+    KEYS.get_or_init(Keys::new)
+}
+
+// key for a piece of the given type and color on `square` (a1 = 0 ... h8 = 63)
+pub fn piece_key(piece_type: PieceType, color: PieceColor, square: usize) -> u64 {
+    let color = match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+        PieceColor::None => return 0,
+    };
+    let piece = match piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+        PieceType::Empty => return 0,
+    };
+
+    keys().pieces[color][piece][square]
+}
+
+// part of the hash whenever black is to move
+pub fn side_key() -> u64 {
+    keys().black_to_move
+}
+
+pub fn castling_key(castling_rights: u8) -> u64 {
+    keys().castling[castling_rights as usize & 0xF]
+}
+
+pub fn en_passant_key(file: usize) -> u64 {
+    keys().en_passant[file]
+}
+
+impl Keys {
+    fn new() -> Self {
+        let mut state = ZOBRIST_SEED;
+        let mut next = || splitmix64(&mut state);
+
+        let mut keys = Keys {
+            pieces: [[[0; 64]; 6]; 2],
+            black_to_move: next(),
+            castling: [0; 16],
+            en_passant: [0; 8],
+        };
+        for key in keys.pieces.iter_mut().flatten().flatten() {
+            *key = next();
+        }
+        for key in keys.castling.iter_mut().chain(keys.en_passant.iter_mut()) {
+            *key = next();
+        }
+
+        keys
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,127 @@
+// perft counts for the well known reference positions, see
+// https://www.chessprogramming.org/Perft_Results. the deeper counts take
+// minutes in a debug build, run them with `cargo test --release -- --ignored`
+
+use chess::board::{Board, START_FEN};
+use chess::perft::{divide, perft};
+
+// castling both ways, en passant, promotions and pins
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+// rook endgame with en passant pins along the rank
+const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+// promotions with capture, castling rights only for black, white in check
+const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+// the same position with colors swapped, should give the same counts
+const POSITION_4_MIRRORED: &str =
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+// promotion into check and castling through an attacked square
+const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+const POSITION_6: &str =
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+// checks perft for each depth from 1, `expected[0]` being the count at depth 1
+fn assert_perft(fen: &str, expected: &[u64]) {
+    let mut board = Board::from_fen(fen).unwrap();
+
+    for (i, &nodes) in expected.iter().enumerate() {
+        let depth = i + 1;
+        assert_eq!(perft(&mut board, depth), nodes, "perft {} of {}", depth, fen);
+    }
+    assert_eq!(board.get_fen(), fen, "perft left the board changed");
+}
+
+#[test]
+fn start_position() {
+    assert_perft(START_FEN, &[20, 400, 8902, 197281]);
+}
+
+#[test]
+fn kiwipete() {
+    assert_perft(KIWIPETE, &[48, 2039, 97862]);
+}
+
+#[test]
+fn position_3() {
+    assert_perft(POSITION_3, &[14, 191, 2812, 43238]);
+}
+
+#[test]
+fn position_4() {
+    assert_perft(POSITION_4, &[6, 264, 9467]);
+    assert_perft(POSITION_4_MIRRORED, &[6, 264, 9467]);
+}
+
+#[test]
+fn position_5() {
+    assert_perft(POSITION_5, &[44, 1486, 62379]);
+}
+
+#[test]
+fn position_6() {
+    assert_perft(POSITION_6, &[46, 2079, 89890]);
+}
+
+#[test]
+fn depth_zero_counts_the_position() {
+    assert_eq!(perft(&mut Board::new(), 0), 1);
+}
+
+#[test]
+fn divide_sums_to_perft() {
+    let mut board = Board::from_fen(KIWIPETE).unwrap();
+    let counts = divide(&mut board, 2);
+
+    assert_eq!(counts.len(), 48);
+    assert_eq!(counts.iter().map(|&(_, nodes)| nodes).sum::<u64>(), perft(&mut board, 2));
+    assert_eq!(board.get_fen(), KIWIPETE);
+}
+
+#[test]
+#[ignore]
+fn start_position_deep() {
+    let mut board = Board::new();
+    assert_eq!(perft(&mut board, 5), 4865609);
+    assert_eq!(perft(&mut board, 6), 119060324);
+}
+
+#[test]
+#[ignore]
+fn kiwipete_deep() {
+    let mut board = Board::from_fen(KIWIPETE).unwrap();
+    assert_eq!(perft(&mut board, 4), 4085603);
+    assert_eq!(perft(&mut board, 5), 193690690);
+}
+
+#[test]
+#[ignore]
+fn position_3_deep() {
+    let mut board = Board::from_fen(POSITION_3).unwrap();
+    assert_eq!(perft(&mut board, 5), 674624);
+    assert_eq!(perft(&mut board, 6), 11030083);
+}
+
+#[test]
+#[ignore]
+fn position_4_deep() {
+    for fen in [POSITION_4, POSITION_4_MIRRORED] {
+        let mut board = Board::from_fen(fen).unwrap();
+        assert_eq!(perft(&mut board, 4), 422333);
+        assert_eq!(perft(&mut board, 5), 15833292);
+    }
+}
+
+#[test]
+#[ignore]
+fn position_5_deep() {
+    let mut board = Board::from_fen(POSITION_5).unwrap();
+    assert_eq!(perft(&mut board, 4), 2103487);
+    assert_eq!(perft(&mut board, 5), 89941194);
+}
+
+#[test]
+#[ignore]
+fn position_6_deep() {
+    let mut board = Board::from_fen(POSITION_6).unwrap();
+    assert_eq!(perft(&mut board, 4), 3894594);
+    assert_eq!(perft(&mut board, 5), 164075551);
+}